@@ -0,0 +1,168 @@
+use bech32::{FromBase32, ToBase32, Variant};
+use cosmwasm_vm::{BackendApi, BackendError, BackendResult, GasInfo};
+use sha2::{Digest, Sha256};
+
+// prefix of the Oraichain addresses
+pub const BECH32_PREFIX: &str = "orai";
+
+// same costs as the mock api of cosmwasm
+const GAS_COST_HUMANIZE: u64 = 44;
+const GAS_COST_CANONICALIZE: u64 = 55;
+
+/// Bech32Api validates and converts the addresses of the contracts like the chain does,
+/// only addresses with the prefix of the chain are valid
+#[derive(Copy, Clone)]
+pub struct Bech32Api {
+    prefix: &'static str,
+}
+
+impl Bech32Api {
+    pub fn new(prefix: &'static str) -> Self {
+        Bech32Api { prefix }
+    }
+}
+
+impl BackendApi for Bech32Api {
+    fn canonical_address(&self, human: &str) -> BackendResult<Vec<u8>> {
+        let gas_info = GasInfo::with_cost(GAS_COST_CANONICALIZE);
+        match canonicalize(self.prefix, human) {
+            Ok(canonical) => (Ok(canonical), gas_info),
+            Err(err) => (Err(BackendError::user_err(err)), gas_info),
+        }
+    }
+
+    fn human_address(&self, canonical: &[u8]) -> BackendResult<String> {
+        let gas_info = GasInfo::with_cost(GAS_COST_HUMANIZE);
+        match humanize(self.prefix, canonical) {
+            Ok(human) => (Ok(human), gas_info),
+            Err(err) => (Err(BackendError::user_err(err)), gas_info),
+        }
+    }
+}
+
+pub fn canonicalize(prefix: &str, human: &str) -> Result<Vec<u8>, String> {
+    let (hrp, data, variant) =
+        bech32::decode(human).map_err(|e| format!("Invalid address {}: {}", human, e))?;
+    if hrp.ne(prefix) {
+        return Err(format!(
+            "Invalid address {}: prefix must be {}",
+            human, prefix
+        ));
+    }
+    if variant != Variant::Bech32 {
+        return Err(format!("Invalid address {}: must be bech32", human));
+    }
+    let canonical = Vec::<u8>::from_base32(&data).map_err(|e| e.to_string())?;
+    humanize(prefix, &canonical)?;
+    Ok(canonical)
+}
+
+pub fn humanize(prefix: &str, canonical: &[u8]) -> Result<String, String> {
+    // the sdk accepts addresses up to 255 bytes
+    if canonical.is_empty() || canonical.len() > 255 {
+        return Err(format!("Invalid address length {}", canonical.len()));
+    }
+    bech32::encode(prefix, canonical.to_base32(), Variant::Bech32).map_err(|e| e.to_string())
+}
+
+// check_prefix makes sure addresses of any length can be written with the prefix and read back
+pub fn check_prefix(prefix: &str) -> Result<(), String> {
+    let addr =
+        humanize(prefix, &[0; 32]).map_err(|e| format!("Invalid prefix {}: {}", prefix, e))?;
+    canonicalize(prefix, &addr).map(|_| ())
+}
+
+pub fn is_address(prefix: &str, addr: &str) -> bool {
+    canonicalize(prefix, addr).is_ok()
+}
+
+// alias_address derives the address standing for a name, like alice or a contract folder,
+// from the first 20 bytes of its hash, the same as a public key address
+pub fn alias_address(prefix: &str, alias: &str) -> String {
+    let hash = Sha256::digest(alias.as_bytes());
+    humanize(prefix, &hash[..20]).unwrap()
+}
+
+// module_address is the address of a module account, like the sdk address.Module
+fn module_address(module: &str, key: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(Sha256::digest(b"module"));
+    hasher.update(module.as_bytes());
+    hasher.update([0]);
+    hasher.update(key);
+    hasher.finalize().to_vec()
+}
+
+// contract_address derives the address of a contract like wasmd does, from the code id
+// and the instance sequence
+pub fn contract_address(prefix: &str, code_id: u64, instance_id: u64) -> String {
+    let mut key = code_id.to_be_bytes().to_vec();
+    key.extend_from_slice(&instance_id.to_be_bytes());
+    humanize(prefix, &module_address("wasm", &key)).unwrap()
+}
+
+// predictable_address derives the address of a contract made by instantiate2,
+// every part is prefixed with its length
+pub fn predictable_address(
+    prefix: &str,
+    checksum: &[u8],
+    creator: &[u8],
+    salt: &[u8],
+    msg: &[u8],
+) -> String {
+    let mut key = vec![];
+    for part in [checksum, creator, salt, msg] {
+        key.extend_from_slice(&(part.len() as u64).to_be_bytes());
+        key.extend_from_slice(part);
+    }
+    humanize(prefix, &module_address("wasm", &key)).unwrap()
+}
+
+#[test]
+fn test_bech32_address() {
+    let alice = alias_address(BECH32_PREFIX, "alice");
+    assert!(alice.starts_with("orai1"));
+    assert_eq!(alice, alias_address(BECH32_PREFIX, "alice"));
+    assert_ne!(alice, alias_address(BECH32_PREFIX, "bob"));
+
+    let canonical = canonicalize(BECH32_PREFIX, &alice).unwrap();
+    assert_eq!(canonical.len(), 20);
+    assert_eq!(humanize(BECH32_PREFIX, &canonical), Ok(alice.to_owned()));
+
+    // other prefixes, bad checksums and plain names are rejected
+    assert!(canonicalize("cosmos", &alice).is_err());
+    let mut typo = alice.to_owned();
+    let last = typo.pop().unwrap();
+    typo.push(if last == 'q' { 'p' } else { 'q' });
+    assert!(!is_address(BECH32_PREFIX, &typo));
+    assert!(!is_address(BECH32_PREFIX, "fake_sender_addr"));
+
+    assert!(check_prefix(BECH32_PREFIX).is_ok());
+    assert!(check_prefix("").is_err());
+    assert!(check_prefix("ORAI").is_err());
+    assert!(check_prefix("or ai").is_err());
+}
+
+#[test]
+fn test_contract_address() {
+    // the first contract of wasmd
+    assert_eq!(
+        contract_address("wasm", 1, 1),
+        "wasm14hj2tavq8fpesdwxxcu44rty3hh90vhujrvcmstl4zr3txmfvw9s0phg4d"
+    );
+
+    // vector of the cosmwasm instantiate2_address tests
+    let hex = |s: &str| {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect::<Vec<u8>>()
+    };
+    let checksum = hex("13a1fc994cc6d1c81b746ee0c0ff6f90043875e0bf1d9be6b7d779fc978dc2a5");
+    let creator = hex("9999999999aaaaaaaaaabbbbbbbbbbcccccccccc");
+    let address = predictable_address(BECH32_PREFIX, &checksum, &creator, b"a", &[]);
+    assert_eq!(
+        canonicalize(BECH32_PREFIX, &address).unwrap(),
+        hex("5e865d3e45ad3e961f77fd77d46543417ced44d924dc3e079b5415ff6775f847")
+    );
+}
@@ -465,6 +465,12 @@ impl Analyzer {
                 translated_list.push(translated["instantiate"].clone());
                 translated_list.push(translated["execute"].clone());
                 translated_list.push(translated["query"].clone());
+                // optional entry points
+                for entry_point in ["migrate", "sudo"].iter() {
+                    if translated[entry_point].is_object() {
+                        translated_list.push(translated[entry_point].clone());
+                    }
+                }
             } else {
                 translated_list.push(translated.clone());
             }
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use cosmwasm_std::{
+    to_binary, AllBalanceResponse, BalanceResponse, BankQuery, Binary, Coin, ContractResult,
+    QuerierResult, SystemError, SystemResult, Uint128,
+};
+
+/// Bank holds the simulator-wide table of balances, every account and contract
+/// reads and moves funds through the same ledger
+#[derive(Clone, Default, Debug)]
+pub struct Bank {
+    balances: HashMap<String, Vec<Coin>>,
+}
+
+impl Bank {
+    pub fn new(balances: &[(&str, &[Coin])]) -> Self {
+        let mut bank = Bank::default();
+        for (addr, coins) in balances.iter() {
+            bank.set_balance(addr, coins.to_vec());
+        }
+        bank
+    }
+
+    pub fn balance(&self, addr: &str, denom: &str) -> Uint128 {
+        self.balances
+            .get(addr)
+            .and_then(|v| v.iter().find(|c| c.denom.eq(denom)).map(|c| c.amount))
+            .unwrap_or_default()
+    }
+
+    pub fn all_balances(&self, addr: &str) -> Vec<Coin> {
+        self.balances.get(addr).cloned().unwrap_or_default()
+    }
+
+    // addresses returns every address holding coins, sorted
+    pub fn addresses(&self) -> Vec<String> {
+        let mut addresses: Vec<String> = self
+            .balances
+            .iter()
+            .filter(|(_, coins)| !coins.is_empty())
+            .map(|(addr, _)| addr.to_owned())
+            .collect();
+        addresses.sort();
+        addresses
+    }
+
+    // set a new balance for the given address and return the old balance
+    pub fn set_balance(&mut self, addr: &str, balance: Vec<Coin>) -> Option<Vec<Coin>> {
+        let mut coins: Vec<Coin> = vec![];
+        for coin in balance {
+            add_coin(&mut coins, &coin);
+        }
+        self.balances.insert(addr.to_string(), coins)
+    }
+
+    pub fn mint(&mut self, addr: &str, amount: &[Coin]) {
+        let coins = self.balances.entry(addr.to_string()).or_default();
+        for coin in amount {
+            add_coin(coins, coin);
+        }
+    }
+
+    pub fn burn(&mut self, addr: &str, amount: &[Coin]) -> Result<(), String> {
+        let mut coins = self.all_balances(addr);
+        for coin in amount {
+            sub_coin(&mut coins, coin).map_err(|e| format!("{} of {}", e, addr))?;
+        }
+        self.balances.insert(addr.to_string(), coins);
+        Ok(())
+    }
+
+    // send moves coins only if the sender has enough of every denom
+    pub fn send(&mut self, from: &str, to: &str, amount: &[Coin]) -> Result<(), String> {
+        self.burn(from, amount)?;
+        self.mint(to, amount);
+        Ok(())
+    }
+
+    pub fn query(&self, request: &BankQuery) -> QuerierResult {
+        let contract_result: ContractResult<Binary> = match request {
+            BankQuery::Balance { address, denom } => {
+                let bank_res = BalanceResponse {
+                    amount: Coin {
+                        amount: self.balance(address, denom),
+                        denom: denom.to_string(),
+                    },
+                };
+                to_binary(&bank_res).into()
+            }
+            BankQuery::AllBalances { address } => {
+                let bank_res = AllBalanceResponse {
+                    amount: self.all_balances(address),
+                };
+                to_binary(&bank_res).into()
+            }
+            _ => {
+                return SystemResult::Err(SystemError::UnsupportedRequest {
+                    kind: "Not implemented".to_string(),
+                })
+            }
+        };
+        // system result is always ok in the mock implementation
+        SystemResult::Ok(contract_result)
+    }
+}
+
+fn add_coin(coins: &mut Vec<Coin>, coin: &Coin) {
+    if coin.amount.is_zero() {
+        return;
+    }
+    match coins.iter_mut().find(|c| c.denom.eq(&coin.denom)) {
+        Some(c) => c.amount += coin.amount,
+        None => {
+            coins.push(coin.clone());
+            coins.sort_by(|a, b| a.denom.cmp(&b.denom));
+        }
+    }
+}
+
+fn sub_coin(coins: &mut Vec<Coin>, coin: &Coin) -> Result<(), String> {
+    if coin.amount.is_zero() {
+        return Ok(());
+    }
+    let index = match coins.iter().position(|c| c.denom.eq(&coin.denom)) {
+        Some(i) if coins[i].amount >= coin.amount => i,
+        _ => return Err(format!("insufficient funds: {}", coin)),
+    };
+    coins[index].amount -= coin.amount;
+    if coins[index].amount.is_zero() {
+        coins.remove(index);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_bank_send() {
+    let mut bank = Bank::new(&[("alice", &[Coin::new(100, "orai")])]);
+    bank.send("alice", "bob", &[Coin::new(40, "orai")]).unwrap();
+    assert_eq!(bank.balance("alice", "orai"), Uint128::from(60u64));
+    assert_eq!(bank.all_balances("bob"), vec![Coin::new(40, "orai")]);
+
+    // not enough funds leaves both balances untouched
+    assert!(bank.send("bob", "alice", &[Coin::new(41, "orai")]).is_err());
+    assert_eq!(bank.balance("bob", "orai"), Uint128::from(40u64));
+    assert_eq!(bank.balance("alice", "orai"), Uint128::from(60u64));
+}
@@ -5,22 +5,29 @@ extern crate serde_json;
 use colored::*;
 
 use cosmwasm_std::{
-    Addr, Attribute, BlockInfo, Coin, ContractInfo, ContractResult, CosmosMsg, Empty, Env,
-    MessageInfo, Timestamp, Uint128,
+    Attribute, Binary, ContractResult, Env, Event, IbcBasicResponse, IbcChannelCloseMsg,
+    IbcChannelConnectMsg, IbcChannelOpenMsg, IbcPacketAckMsg, IbcPacketReceiveMsg,
+    IbcPacketTimeoutMsg, IbcReceiveResponse, MessageInfo, Reply, Response,
 };
 
-use cosmwasm_vm::{Instance, InstanceOptions, Size};
+use cosmwasm_vm::{Instance, InstanceOptions, Size, VmResult};
 
-use crate::contract_vm::querier::WasmHandler;
+use crate::contract_vm::address::Bech32Api;
+use crate::contract_vm::simulator::Chain;
 use crate::contract_vm::{analyzer, mock};
-use cosmwasm_vm::testing::MockApi;
+use std::cell::RefCell;
 use std::fmt::Write;
+use std::rc::{Rc, Weak};
 
-const DEFAULT_CONTRACT_BALANCE: u64 = 10_000_000_000_000_000;
 const DEFAULT_GAS_LIMIT: u64 = 500_000_000_000_000;
 const DEFAULT_MEMORY_LIMIT: Size = Size::mebi(16);
 pub const DENOM: &str = "orai";
 pub const CHAIN_ID: &str = "Oraichain";
+// block the simulated chain starts at
+pub const BLOCK_HEIGHT: u64 = 12_345;
+pub const BLOCK_TIME: u64 = 1_571_797_419;
+// seconds between two blocks, block time moves with height
+pub const BLOCK_INTERVAL: u64 = 5;
 const SCHEMA_FOLDER: &str = "schema";
 
 // Instance
@@ -29,31 +36,23 @@ const DEFAULT_INSTANCE_OPTIONS: InstanceOptions = InstanceOptions {
     print_debug: false,
 };
 
-pub static mut BLOCK_HEIGHT: u64 = 12_345;
-// callback execute for Handle Response, like send native balance, execute other smart contract
-pub type CallBackHandler = fn(&str, Vec<CosmosMsg>) -> Vec<Attribute>;
-
+/// ContractInstance is a compiled contract with its storage, the simulator gives it
+/// the env of every call and handles the messages of its responses
 pub struct ContractInstance {
-    pub instance: Instance<MockApi, mock::MockStorage, mock::MockQuerier<mock::SpecialQuery>>,
+    pub instance: Instance<Bech32Api, mock::MockStorage, mock::MockQuerier>,
     pub wasm_file: String,
-    pub env: Env,
-    pub analyzer: analyzer::Analyzer,
-    pub execute_callback: CallBackHandler,
+    // shared so that the terminal can read the schema while the contract runs
+    pub analyzer: Rc<analyzer::Analyzer>,
 }
 
 impl ContractInstance {
     pub fn new_instance(
         wasm_file: &str,
-        contract_addr: &str,
-        query_wasm: WasmHandler,
+        api: Bech32Api,
+        chain: Weak<RefCell<Chain>>,
         storage: &mock::MockStorage,
-        execute_callback: CallBackHandler,
     ) -> Result<Self, String> {
-        let balances = &[Coin {
-            denom: DENOM.to_string(),
-            amount: Uint128::from(DEFAULT_CONTRACT_BALANCE),
-        }];
-        let deps = mock::new_mock(balances, contract_addr, query_wasm, storage.to_owned());
+        let deps = mock::new_mock(api, chain, storage.to_owned());
 
         let wasm = match analyzer::load_data_from_file(wasm_file) {
             Err(e) => return Err(e),
@@ -78,44 +77,19 @@ impl ContractInstance {
             }
             Ok(i) => i,
         };
-        return Ok(ContractInstance::make_instance(
-            inst,
-            wasm_file,
-            contract_addr,
-            execute_callback,
-        ));
+        return Ok(ContractInstance::make_instance(inst, wasm_file));
     }
 
     fn make_instance(
-        inst: cosmwasm_vm::Instance<
-            MockApi,
-            mock::MockStorage,
-            mock::MockQuerier<mock::SpecialQuery>,
-        >,
+        inst: cosmwasm_vm::Instance<Bech32Api, mock::MockStorage, mock::MockQuerier>,
         file: &str,
-        contract_addr: &str,
-        execute_callback: CallBackHandler,
     ) -> ContractInstance {
         let alz = analyzer::from_json_schema(file, SCHEMA_FOLDER);
 
-        unsafe {
-            ContractInstance {
-                instance: inst,
-                wasm_file: file.to_string(),
-                env: Env {
-                    block: BlockInfo {
-                        height: BLOCK_HEIGHT,
-                        time: Timestamp::from_seconds(1_571_797_419),
-                        chain_id: CHAIN_ID.to_string(),
-                    },
-                    contract: ContractInfo {
-                        address: Addr::unchecked(contract_addr),
-                    },
-                    transaction: None,
-                },
-                analyzer: alz,
-                execute_callback,
-            }
+        ContractInstance {
+            instance: inst,
+            wasm_file: file.to_string(),
+            analyzer: Rc::new(alz),
         }
     }
 
@@ -130,7 +104,7 @@ impl ContractInstance {
         }
     }
 
-    fn dump_result(key: &str, value: &[u8], len: usize) -> String {
+    pub fn dump_result(key: &str, value: &[u8], len: usize) -> String {
         let mut value_str = match std::str::from_utf8(value) {
             Ok(result) => result.to_string(),
             _ => "".to_string(),
@@ -152,140 +126,189 @@ impl ContractInstance {
         value_str
     }
 
-    pub fn instantiate(&mut self, param: &str, info: &MessageInfo) -> String {
-        self.instantiate_raw(param.as_bytes(), info)
+    pub fn dump_events(events: &[Event], data: Option<&Binary>) {
+        for event in events.iter() {
+            println!("{}", format!("[{}]", event.ty).green().bold());
+            ContractInstance::dump_results(&event.attributes);
+        }
+        if let Some(data) = data {
+            ContractInstance::dump_result("data", data.as_slice(), 4);
+        }
+    }
+
+    // gas_used returns the gas used by the instance since it was compiled
+    pub fn gas_used(&self) -> u64 {
+        DEFAULT_GAS_LIMIT.saturating_sub(self.instance.get_gas_left())
     }
 
-    pub fn instantiate_raw(&mut self, param: &[u8], info: &MessageInfo) -> String {
-        let result = cosmwasm_vm::call_instantiate::<_, _, _, Empty>(
+    fn contract_result<T>(result: VmResult<ContractResult<T>>) -> Result<T, String> {
+        match result {
+            Ok(ContractResult::Ok(response)) => Ok(response),
+            Ok(ContractResult::Err(err)) => Err(err),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    pub fn run_instantiate(
+        &mut self,
+        env: &Env,
+        param: &[u8],
+        info: &MessageInfo,
+    ) -> Result<Response<mock::SpecialMsg>, String> {
+        let result = cosmwasm_vm::call_instantiate::<_, _, _, mock::SpecialMsg>(
             &mut self.instance,
-            &self.env,
+            env,
             info,
             param,
         );
 
-        match result {
-            Ok(response) => match response {
-                ContractResult::Ok(val) => {
-                    ContractInstance::dump_results(&(self.execute_callback)(
-                        self.env.contract.address.as_str(),
-                        val.messages.into_iter().map(|msg| msg.msg).collect(),
-                    ));
-
-                    ContractInstance::dump_results(&val.attributes);
-
-                    // simulate block height increase for later expire check
-                    unsafe {
-                        BLOCK_HEIGHT += 1;
-                        self.env.block.height = BLOCK_HEIGHT;
-                    }
-
-                    r#"{"message":"init succeeded"}"#.to_string()
-                }
-                ContractResult::Err(err) => {
-                    println!("{}", err.red());
-                    format!(r#"{{"error":"{}"}}"#, err)
-                }
-            },
-            Err(err) => {
-                println!("{}", err.to_string().red());
-                format!(r#"{{"error":"{}"}}"#, err.to_string())
-            }
-        }
+        ContractInstance::contract_result(result)
     }
 
-    pub fn execute(&mut self, param: &str, info: &MessageInfo) -> String {
-        self.execute_raw(param.as_bytes(), info)
+    pub fn run_execute(
+        &mut self,
+        env: &Env,
+        param: &[u8],
+        info: &MessageInfo,
+    ) -> Result<Response<mock::SpecialMsg>, String> {
+        let result = cosmwasm_vm::call_execute::<_, _, _, mock::SpecialMsg>(
+            &mut self.instance,
+            env,
+            info,
+            param,
+        );
+
+        ContractInstance::contract_result(result)
     }
 
-    pub fn execute_raw(&mut self, param: &[u8], info: &MessageInfo) -> String {
+    // run_migrate runs the migrate export, the code must be swapped by the caller beforehand
+    pub fn run_migrate(
+        &mut self,
+        env: &Env,
+        param: &[u8],
+    ) -> Result<Response<mock::SpecialMsg>, String> {
         let result =
-            cosmwasm_vm::call_execute::<_, _, _, Empty>(&mut self.instance, &self.env, info, param);
+            cosmwasm_vm::call_migrate::<_, _, _, mock::SpecialMsg>(&mut self.instance, env, param);
 
-        match result {
-            Ok(response) => match response {
-                ContractResult::Ok(val) => {
-                    ContractInstance::dump_results(&(self.execute_callback)(
-                        self.env.contract.address.as_str(),
-                        val.messages.into_iter().map(|msg| msg.msg).collect(),
-                    ));
-
-                    ContractInstance::dump_results(&val.attributes);
-
-                    // simulate block height increase for later expire check
-                    unsafe {
-                        BLOCK_HEIGHT += 1;
-                        self.env.block.height = BLOCK_HEIGHT;
-                    }
-
-                    r#"{"message":"execute succeeded"}"#.to_string()
-                }
-                ContractResult::Err(err) => {
-                    println!("{}", err.red());
-                    format!(r#"{{"error":"{}"}}"#, err)
-                }
-            },
-
-            Err(err) => {
-                println!("{}", err.to_string().red());
-                format!(r#"{{"error":"{}"}}"#, err.to_string())
-            }
-        }
+        ContractInstance::contract_result(result)
     }
 
-    pub fn query(&mut self, param: &str) -> String {
-        self.query_raw(param.as_bytes())
+    // run_sudo calls the privileged entry point, like the chain governance or a module would
+    pub fn run_sudo(
+        &mut self,
+        env: &Env,
+        param: &[u8],
+    ) -> Result<Response<mock::SpecialMsg>, String> {
+        let result =
+            cosmwasm_vm::call_sudo::<_, _, _, mock::SpecialMsg>(&mut self.instance, env, param);
+
+        ContractInstance::contract_result(result)
     }
 
-    pub fn query_raw(&mut self, param: &[u8]) -> String {
-        // check param if it is custom, we will try to check for oracle special query to implement, otherwise forward
-        // to virtual machine
-        let result = cosmwasm_vm::call_query(&mut self.instance, &self.env, param);
+    pub fn run_reply(
+        &mut self,
+        env: &Env,
+        reply: &Reply,
+    ) -> Result<Response<mock::SpecialMsg>, String> {
+        let result =
+            cosmwasm_vm::call_reply::<_, _, _, mock::SpecialMsg>(&mut self.instance, env, reply);
 
-        match result {
-            Ok(response) => match response {
-                ContractResult::Ok(val) => {
-                    ContractInstance::dump_result("query data", val.as_slice(), 10)
-                }
-                ContractResult::Err(err) => {
-                    println!("{}", err.red());
-                    format!(r#"{{"error":"{}"}}"#, err)
-                }
-            },
-            Err(err) => {
-                println!("{}", err.to_string().red());
-                format!(r#"{{"error":"{}"}}"#, err.to_string())
-            }
-        }
+        ContractInstance::contract_result(result)
     }
 
-    pub fn call(&mut self, func_type: &str, param: &str, info: &MessageInfo) -> String {
-        println!();
-        println!("===========================call started===========================");
-        println!(
-            "executing func [{}] , params is {}",
-            func_type.green().bold(),
-            param.yellow()
+    // run_ibc_channel_open returns the version proposed by the contract, if it changes it
+    pub fn run_ibc_channel_open(
+        &mut self,
+        env: &Env,
+        msg: &IbcChannelOpenMsg,
+    ) -> Result<Option<String>, String> {
+        let result = cosmwasm_vm::call_ibc_channel_open(&mut self.instance, env, msg);
+
+        let response = ContractInstance::contract_result(result)?;
+        Ok(response.map(|r| r.version))
+    }
+
+    pub fn run_ibc_channel_connect(
+        &mut self,
+        env: &Env,
+        msg: &IbcChannelConnectMsg,
+    ) -> Result<IbcBasicResponse<mock::SpecialMsg>, String> {
+        let result = cosmwasm_vm::call_ibc_channel_connect::<_, _, _, mock::SpecialMsg>(
+            &mut self.instance,
+            env,
+            msg,
         );
-        let gas_init = self.instance.get_gas_left();
-        let res = match func_type {
-            "instantiate" => self.instantiate(param, info),
-            "execute" => self.execute(param, info),
-            "query" => self.query(param),
-            _ => {
-                println!("wrong dispatcher call {}", func_type.green().bold());
-                format!(r#"{{"error":"wrong dispatcher call {}"}}"#, func_type)
-            }
-        };
 
-        let gas_used = gas_init - self.instance.get_gas_left();
-        println!(
-            "{}   : {}",
-            "gas used".blue().bold(),
-            gas_used.to_string().yellow()
+        ContractInstance::contract_result(result)
+    }
+
+    pub fn run_ibc_channel_close(
+        &mut self,
+        env: &Env,
+        msg: &IbcChannelCloseMsg,
+    ) -> Result<IbcBasicResponse<mock::SpecialMsg>, String> {
+        let result = cosmwasm_vm::call_ibc_channel_close::<_, _, _, mock::SpecialMsg>(
+            &mut self.instance,
+            env,
+            msg,
         );
-        println!("===========================call finished===========================");
-        println!();
-        return res;
+
+        ContractInstance::contract_result(result)
+    }
+
+    // run_ibc_packet_receive returns the acknowledgement written by the contract along with its messages
+    pub fn run_ibc_packet_receive(
+        &mut self,
+        env: &Env,
+        msg: &IbcPacketReceiveMsg,
+    ) -> Result<IbcReceiveResponse<mock::SpecialMsg>, String> {
+        let result = cosmwasm_vm::call_ibc_packet_receive::<_, _, _, mock::SpecialMsg>(
+            &mut self.instance,
+            env,
+            msg,
+        );
+
+        ContractInstance::contract_result(result)
+    }
+
+    pub fn run_ibc_packet_ack(
+        &mut self,
+        env: &Env,
+        msg: &IbcPacketAckMsg,
+    ) -> Result<IbcBasicResponse<mock::SpecialMsg>, String> {
+        let result = cosmwasm_vm::call_ibc_packet_ack::<_, _, _, mock::SpecialMsg>(
+            &mut self.instance,
+            env,
+            msg,
+        );
+
+        ContractInstance::contract_result(result)
+    }
+
+    pub fn run_ibc_packet_timeout(
+        &mut self,
+        env: &Env,
+        msg: &IbcPacketTimeoutMsg,
+    ) -> Result<IbcBasicResponse<mock::SpecialMsg>, String> {
+        let result = cosmwasm_vm::call_ibc_packet_timeout::<_, _, _, mock::SpecialMsg>(
+            &mut self.instance,
+            env,
+            msg,
+        );
+
+        ContractInstance::contract_result(result)
+    }
+
+    pub fn run_query(&mut self, env: &Env, param: &[u8]) -> Result<Binary, String> {
+        let result = cosmwasm_vm::call_query(&mut self.instance, env, param);
+
+        ContractInstance::contract_result(result)
+    }
+
+    // storage returns a copy of the contract storage, to compile another code over it
+    pub fn storage(&mut self) -> Result<mock::MockStorage, String> {
+        self.instance
+            .with_storage(|storage| Ok(storage.clone()))
+            .map_err(|e| e.to_string())
     }
 }
@@ -0,0 +1,186 @@
+use std::collections::VecDeque;
+
+use cosmwasm_std::{
+    Binary, BlockInfo, IbcChannel, IbcEndpoint, IbcOrder, IbcPacket, IbcTimeout, IbcTimeoutBlock,
+};
+
+use crate::contract_vm::address::alias_address;
+
+const RELAYER: &str = "relayer";
+const CONNECTION_ID: &str = "connection-0";
+
+// relayer_addr is the account relaying the packets, contracts may validate it
+pub fn relayer_addr(prefix: &str) -> String {
+    alias_address(prefix, RELAYER)
+}
+
+#[derive(Clone, Debug)]
+pub struct ChannelEnd {
+    pub contract_addr: String,
+    pub endpoint: IbcEndpoint,
+    next_sequence: u64,
+}
+
+/// Channel links two loaded contracts, both ends live on the simulated chain
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub ends: [ChannelEnd; 2],
+    pub order: IbcOrder,
+    pub version: String,
+    pub open: bool,
+}
+
+impl Channel {
+    // the channel as seen from one of its ends
+    pub fn ibc_channel(&self, side: usize) -> IbcChannel {
+        IbcChannel::new(
+            self.ends[side].endpoint.clone(),
+            self.ends[1 - side].endpoint.clone(),
+            self.order.clone(),
+            self.version.clone(),
+            CONNECTION_ID,
+        )
+    }
+}
+
+/// Relayer keeps the channels opened between contracts and the packets waiting
+/// to be relayed, every step is triggered by the user
+#[derive(Clone, Default, Debug)]
+pub struct Relayer {
+    pub channels: Vec<Channel>,
+    // packets sent but not received yet
+    pub packets: VecDeque<IbcPacket>,
+    // acknowledgements written by the receiver, not relayed back yet
+    pub acks: VecDeque<(IbcPacket, Binary)>,
+}
+
+impl Relayer {
+    pub fn port_id(contract_addr: &str) -> String {
+        format!("wasm.{}", contract_addr)
+    }
+
+    // new_channel registers a channel in init state and returns its index
+    pub fn new_channel(
+        &mut self,
+        contract_a: &str,
+        contract_b: &str,
+        order: IbcOrder,
+        version: &str,
+    ) -> usize {
+        let index = self.channels.len();
+        let end = |contract_addr: &str, channel: usize| ChannelEnd {
+            contract_addr: contract_addr.to_string(),
+            endpoint: IbcEndpoint {
+                port_id: Relayer::port_id(contract_addr),
+                channel_id: format!("channel-{}", channel),
+            },
+            next_sequence: 1,
+        };
+        self.channels.push(Channel {
+            ends: [end(contract_a, index * 2), end(contract_b, index * 2 + 1)],
+            order,
+            version: version.to_string(),
+            open: false,
+        });
+        index
+    }
+
+    // find_end returns the channel index and the side owned by the contract
+    pub fn find_end(&self, contract_addr: &str, channel_id: &str) -> Option<(usize, usize)> {
+        self.channels
+            .iter()
+            .enumerate()
+            .find_map(|(index, channel)| {
+                channel
+                    .ends
+                    .iter()
+                    .position(|end| {
+                        end.contract_addr.eq(contract_addr)
+                            && end.endpoint.channel_id.eq(channel_id)
+                    })
+                    .map(|side| (index, side))
+            })
+    }
+
+    // contract_of returns the contract bound to an endpoint
+    pub fn contract_of(&self, endpoint: &IbcEndpoint) -> Option<String> {
+        self.channels
+            .iter()
+            .flat_map(|channel| channel.ends.iter())
+            .find(|end| end.endpoint.eq(endpoint))
+            .map(|end| end.contract_addr.clone())
+    }
+
+    pub fn send_packet(
+        &mut self,
+        contract_addr: &str,
+        channel_id: &str,
+        data: Binary,
+        timeout: IbcTimeout,
+    ) -> Result<IbcPacket, String> {
+        let (index, side) = match self.find_end(contract_addr, channel_id) {
+            Some(found) => found,
+            None => {
+                return Err(format!(
+                    "channel {} not found for {}",
+                    channel_id, contract_addr
+                ))
+            }
+        };
+        let channel = &mut self.channels[index];
+        if !channel.open {
+            return Err(format!("channel {} is not open", channel_id));
+        }
+        let packet = IbcPacket::new(
+            data,
+            channel.ends[side].endpoint.clone(),
+            channel.ends[1 - side].endpoint.clone(),
+            channel.ends[side].next_sequence,
+            timeout,
+        );
+        channel.ends[side].next_sequence += 1;
+        self.packets.push_back(packet.clone());
+        Ok(packet)
+    }
+}
+
+// is_timed_out checks the packet timeout against the block of the receiving contract
+pub fn is_timed_out(packet: &IbcPacket, block: &BlockInfo) -> bool {
+    let height_passed = packet.timeout.block().is_some_and(|timeout| {
+        IbcTimeoutBlock {
+            revision: timeout.revision,
+            height: block.height,
+        } >= timeout
+    });
+    let time_passed = packet
+        .timeout
+        .timestamp()
+        .is_some_and(|timeout| block.time >= timeout);
+    height_passed || time_passed
+}
+
+#[test]
+fn test_send_packet() {
+    let mut relayer = Relayer::default();
+    let index = relayer.new_channel("ping", "pong", IbcOrder::Unordered, "ping-1");
+    let timeout = IbcTimeout::with_block(IbcTimeoutBlock {
+        revision: 0,
+        height: 100,
+    });
+
+    // closed channel refuses packets
+    assert!(relayer
+        .send_packet("ping", "channel-0", Binary::from(b"hi"), timeout.clone())
+        .is_err());
+
+    relayer.channels[index].open = true;
+    relayer
+        .send_packet("ping", "channel-0", Binary::from(b"hi"), timeout.clone())
+        .unwrap();
+    let packet = relayer
+        .send_packet("ping", "channel-0", Binary::from(b"hi"), timeout)
+        .unwrap();
+    assert_eq!(packet.sequence, 2);
+    assert_eq!(relayer.contract_of(&packet.dest), Some("pong".to_string()));
+    assert_eq!(relayer.packets.len(), 2);
+}
@@ -3,19 +3,26 @@ use std::convert::TryInto;
 use std::ops::{Bound, RangeBounds};
 
 use cosmwasm_std::{
-    from_slice, to_binary, to_vec, Binary, Coin, ContractResult, CustomQuery, Empty,
-    Querier as StdQuerier, QuerierResult, QueryRequest, SystemError, SystemResult,
+    from_slice, to_binary, to_vec, Binary, Coin, ContractResult, CustomMsg, CustomQuery, Event,
+    Querier as StdQuerier, QuerierResult, QueryRequest, SubMsgResponse, SystemError, SystemResult,
 };
 
 use cosmwasm_std::{Order, Record};
 
-use cosmwasm_vm::testing::MockApi;
 use cosmwasm_vm::{Backend, BackendError, BackendResult, GasInfo, Querier, Storage};
-use serde::de::DeserializeOwned;
+use schemars::JsonSchema;
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
 use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::rc::Weak;
 
-use crate::contract_vm::querier::{CustomHandler, StdMockQuerier, WasmHandler};
+use crate::contract_vm::address::Bech32Api;
+use crate::contract_vm::bank::Bank;
+use crate::contract_vm::querier::StdMockQuerier;
+use crate::contract_vm::simulator::Chain;
 use crate::contract_vm::watcher;
 
 /// Implement MockQuerier
@@ -29,40 +36,22 @@ const GAS_COST_QUERY_RESPONSE_MULTIPLIER: u64 = 100;
 const GAS_COST_LAST_ITERATION: u64 = 37;
 const GAS_COST_RANGE: u64 = 11;
 
-/// MockQuerier holds an immutable table of bank balances
-/// TODO: also allow querying contracts
-pub struct MockQuerier<C: CustomQuery + DeserializeOwned = Empty> {
-    querier: StdMockQuerier<C>,
+/// MockQuerier answers the queries of a contract from the simulated chain
+pub struct MockQuerier {
+    querier: StdMockQuerier,
 }
 
-impl<C: CustomQuery + DeserializeOwned> MockQuerier<C> {
-    pub fn new(
-        balances: &[(&str, &[Coin])],
-        custom_handler: CustomHandler<C>,
-        wasm_handler: WasmHandler,
-    ) -> Self {
+impl MockQuerier {
+    pub fn new(chain: Weak<RefCell<Chain>>) -> Self {
         MockQuerier {
-            querier: StdMockQuerier::new(balances, custom_handler, wasm_handler),
+            querier: StdMockQuerier::new(chain),
         }
     }
-
-    // set a new balance for the given address and return the old balance
-    pub fn update_balance(&mut self, addr: String, balance: Vec<Coin>) -> Option<Vec<Coin>> {
-        self.querier.update_balance(addr, balance)
-    }
-
-    pub fn with_custom_handler<CH: 'static>(mut self, handler: CH) -> Self
-    where
-        CH: Fn(&C) -> MockQuerierCustomHandlerResult,
-    {
-        self.querier = self.querier.with_custom_handler(handler);
-        self
-    }
 }
 
-impl<C: CustomQuery + DeserializeOwned> StdQuerier for StdMockQuerier<C> {
+impl StdQuerier for StdMockQuerier {
     fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
-        let request: QueryRequest<C> = match from_slice(bin_request) {
+        let request: QueryRequest<SpecialQuery> = match from_slice(bin_request) {
             Ok(v) => v,
             Err(e) => {
                 return SystemResult::Err(SystemError::InvalidRequest {
@@ -75,7 +64,7 @@ impl<C: CustomQuery + DeserializeOwned> StdQuerier for StdMockQuerier<C> {
     }
 }
 
-impl<C: CustomQuery + DeserializeOwned> Querier for MockQuerier<C> {
+impl Querier for MockQuerier {
     fn query_raw(
         &self,
         bin_request: &[u8],
@@ -124,18 +113,273 @@ impl MockQuerier {
     }
 }
 
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(transparent)]
+/// SpecialQuery keeps QueryRequest::Custom as raw json, so any blockchain extension can be answered
+pub struct SpecialQuery(pub Value);
+
+impl<'de> Deserialize<'de> for SpecialQuery {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        JsonSeed.deserialize(deserializer).map(SpecialQuery)
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(transparent)]
+/// SpecialMsg keeps CosmosMsg::Custom as raw json, the custom message fixtures apply its effects
+pub struct SpecialMsg(pub Value);
+
+impl<'de> Deserialize<'de> for SpecialMsg {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        JsonSeed.deserialize(deserializer).map(SpecialMsg)
+    }
+}
+
+impl CustomMsg for SpecialMsg {}
+
+// JsonSeed reads any json into a Value, map keys are read as str since
+// the contract json deserializer does not support owned string keys
+struct JsonSeed;
+
+impl<'de> DeserializeSeed<'de> for JsonSeed {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+struct KeySeed;
+
+impl<'de> DeserializeSeed<'de> for KeySeed {
+    type Value = String;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<String, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> Visitor<'de> for KeySeed {
+    type Value = String;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a json key")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<String, E> {
+        Ok(v.to_string())
+    }
+}
+
+impl<'de> Visitor<'de> for JsonSeed {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any json value")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut values = vec![];
+        while let Some(value) = seq.next_element_seed(JsonSeed)? {
+            values.push(value);
+        }
+        Ok(Value::Array(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut values = serde_json::Map::new();
+        while let Some(key) = map.next_key_seed(KeySeed)? {
+            values.insert(key, map.next_value_seed(JsonSeed)?);
+        }
+        Ok(Value::Object(values))
+    }
+}
+
+impl CustomQuery for SpecialQuery {}
+
+/// SpecialQueryFixture answers the custom queries containing its request,
+/// with the response or the contract error
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SpecialQueryFixture {
+    pub request: Value,
+    #[serde(default)]
+    pub response: Value,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+// matches checks the value has every field of the pattern, other values must be equal
+fn matches(pattern: &Value, value: &Value) -> bool {
+    match (pattern, value) {
+        (Value::Object(pattern), Value::Object(value)) => pattern
+            .iter()
+            .all(|(key, p)| value.get(key).is_some_and(|v| matches(p, v))),
+        _ => pattern.eq(value),
+    }
+}
+
+// custom_query_execute answers with the first fixture matching the query
+pub fn custom_query_execute(
+    fixtures: &[SpecialQueryFixture],
+    query: &SpecialQuery,
+) -> MockQuerierCustomHandlerResult {
+    match fixtures.iter().find(|f| matches(&f.request, &query.0)) {
+        None => SystemResult::Err(SystemError::UnsupportedRequest {
+            kind: format!("custom query {}", query.0),
+        }),
+        Some(SpecialQueryFixture {
+            error: Some(err), ..
+        }) => SystemResult::Ok(ContractResult::Err(err.to_owned())),
+        Some(fixture) => match serde_json::to_vec(&fixture.response) {
+            Ok(response) => SystemResult::Ok(ContractResult::Ok(Binary::from(response))),
+            Err(err) => SystemResult::Ok(ContractResult::Err(err.to_string())),
+        },
+    }
+}
+
+/// BankEffect is a change a custom message makes to the bank ledger, fields starting
+/// with / are json pointers into the message and $sender is the contract sending it
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "snake_case")]
-/// An implementation of QueryRequest::Custom to show this works and can be extended in the contract
-pub enum SpecialQuery {
-    // define custom query here for blockchain extension
+pub enum BankEffect {
+    Mint {
+        to: String,
+        denom: String,
+        amount: String,
+    },
+    Burn {
+        from: String,
+        denom: String,
+        amount: String,
+    },
+    Send {
+        from: String,
+        to: String,
+        denom: String,
+        amount: String,
+    },
 }
 
-impl CustomQuery for SpecialQuery {}
+/// SpecialMsgFixture handles the custom messages containing its msg, like a token factory
+/// minting with {"mint":{"to":"/mint_tokens/mint_to_address","denom":"/mint_tokens/denom","amount":"/mint_tokens/amount"}}
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SpecialMsgFixture {
+    pub msg: Value,
+    #[serde(default)]
+    pub effects: Vec<BankEffect>,
+    #[serde(default)]
+    pub data: Option<Binary>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
 
-pub fn custom_query_execute(_query: &SpecialQuery) -> MockQuerierCustomHandlerResult {
-    SystemResult::Err(SystemError::UnsupportedRequest {
-        kind: "Not implemented".to_string(),
+// resolve reads a fixture field from the message
+fn resolve(field: &str, msg: &Value, sender: &str) -> Result<String, String> {
+    if field.eq("$sender") {
+        return Ok(sender.to_string());
+    }
+    if !field.starts_with('/') {
+        return Ok(field.to_string());
+    }
+    match msg.pointer(field) {
+        Some(Value::String(value)) => Ok(value.to_owned()),
+        Some(Value::Number(value)) => Ok(value.to_string()),
+        _ => Err(format!("{} not found in custom message", field)),
+    }
+}
+
+fn resolve_coin(denom: &str, amount: &str, msg: &Value, sender: &str) -> Result<Coin, String> {
+    let amount = resolve(amount, msg, sender)?;
+    match amount.parse::<u128>() {
+        Ok(amount) => Ok(Coin::new(amount, resolve(denom, msg, sender)?)),
+        Err(err) => Err(format!("invalid amount {}: {}", amount, err)),
+    }
+}
+
+// custom_msg_execute applies the effects of the first fixture matching the message
+pub fn custom_msg_execute(
+    fixtures: &[SpecialMsgFixture],
+    bank: &mut Bank,
+    sender: &str,
+    msg: &SpecialMsg,
+) -> Result<SubMsgResponse, String> {
+    let fixture = match fixtures.iter().find(|f| matches(&f.msg, &msg.0)) {
+        None => return Err(format!("Unsupported custom message: {}", msg.0)),
+        Some(fixture) => fixture,
+    };
+    if let Some(err) = &fixture.error {
+        return Err(err.to_owned());
+    }
+
+    let mut events = vec![];
+    for effect in fixture.effects.iter() {
+        let event = match effect {
+            BankEffect::Mint { to, denom, amount } => {
+                let to = resolve(to, &msg.0, sender)?;
+                let coin = resolve_coin(denom, amount, &msg.0, sender)?;
+                bank.mint(&to, &[coin.to_owned()]);
+                Event::new("mint")
+                    .add_attribute("recipient", to)
+                    .add_attribute("amount", coin.to_string())
+            }
+            BankEffect::Burn {
+                from,
+                denom,
+                amount,
+            } => {
+                let from = resolve(from, &msg.0, sender)?;
+                let coin = resolve_coin(denom, amount, &msg.0, sender)?;
+                bank.burn(&from, &[coin.to_owned()])?;
+                Event::new("burn")
+                    .add_attribute("burner", from)
+                    .add_attribute("amount", coin.to_string())
+            }
+            BankEffect::Send {
+                from,
+                to,
+                denom,
+                amount,
+            } => {
+                let from = resolve(from, &msg.0, sender)?;
+                let to = resolve(to, &msg.0, sender)?;
+                let coin = resolve_coin(denom, amount, &msg.0, sender)?;
+                bank.send(&from, &to, &[coin.to_owned()])?;
+                Event::new("transfer")
+                    .add_attribute("recipient", to)
+                    .add_attribute("sender", from)
+                    .add_attribute("amount", coin.to_string())
+            }
+        };
+        events.push(event);
+    }
+
+    Ok(SubMsgResponse {
+        events,
+        data: fixture.data.to_owned(),
     })
 }
 
@@ -149,6 +393,11 @@ pub struct Iter {
 pub struct MockStorage {
     pub data: BTreeMap<Vec<u8>, Vec<u8>>,
     pub iterators: HashMap<u32, Iter>,
+    // undo log of the open checkpoints, each entry keeps the previous value of a key
+    journal: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    checkpoints: Vec<usize>,
+    // print every change, for the terminal
+    pub log: bool,
 }
 
 impl MockStorage {
@@ -156,6 +405,40 @@ impl MockStorage {
         MockStorage::default()
     }
 
+    pub fn checkpoint(&mut self) {
+        self.checkpoints.push(self.journal.len());
+    }
+
+    // commit keeps the changes made since the last checkpoint
+    pub fn commit(&mut self) {
+        self.checkpoints.pop();
+        if self.checkpoints.is_empty() {
+            self.journal.clear();
+        }
+    }
+
+    // rollback undoes the changes made since the last checkpoint
+    pub fn rollback(&mut self) {
+        let mark = match self.checkpoints.pop() {
+            None => return,
+            Some(m) => m,
+        };
+        while self.journal.len() > mark {
+            let (key, value) = self.journal.pop().unwrap();
+            match value {
+                Some(v) => self.data.insert(key, v),
+                None => self.data.remove(&key),
+            };
+        }
+    }
+
+    fn record(&mut self, key: &[u8]) {
+        if !self.checkpoints.is_empty() {
+            self.journal
+                .push((key.to_vec(), self.data.get(key).cloned()));
+        }
+    }
+
     pub fn all(&mut self, iterator_id: u32) -> BackendResult<Vec<Record>> {
         let mut out: Vec<Record> = Vec::new();
         let mut total = GasInfo::free();
@@ -243,16 +526,22 @@ impl Storage for MockStorage {
 
     // watch changes
     fn set(&mut self, key: &[u8], value: &[u8]) -> BackendResult<()> {
+        self.record(key);
         self.data.insert(key.to_vec(), value.to_vec());
         let gas_info = GasInfo::with_externally_used((key.len() + value.len()) as u64);
-        watcher::logger_storage_event_insert(key, value);
+        if self.log {
+            watcher::logger_storage_event_insert(key, value);
+        }
         (Ok(()), gas_info)
     }
 
     fn remove(&mut self, key: &[u8]) -> BackendResult<()> {
+        self.record(key);
         self.data.remove(key);
         let gas_info = GasInfo::with_externally_used(key.len() as u64);
-        watcher::logger_storage_event_remove(key);
+        if self.log {
+            watcher::logger_storage_event_remove(key);
+        }
         (Ok(()), gas_info)
     }
 }
@@ -274,21 +563,80 @@ fn clone_item<T: Clone>(item_ref: BTreeMapPairRef<T>) -> Record<T> {
 }
 
 pub fn new_mock(
-    contract_balance: &[Coin],
-    contract_addr: &str,
-    wasm_handler: WasmHandler,
+    api: Bech32Api,
+    chain: Weak<RefCell<Chain>>,
     storage: MockStorage,
-) -> Backend<MockApi, MockStorage, MockQuerier<SpecialQuery>> {
-    // update custom_querier
-    let custom_querier: MockQuerier<SpecialQuery> = MockQuerier::new(
-        &[(contract_addr, contract_balance)],
-        Box::new(|query| -> MockQuerierCustomHandlerResult { custom_query_execute(&query) }),
-        wasm_handler,
-    );
-
+) -> Backend<Bech32Api, MockStorage, MockQuerier> {
+    // balances, delegations and other contracts are read from the simulated chain
     Backend {
-        api: MockApi::new(24),
+        api,
         storage,
-        querier: custom_querier,
+        querier: MockQuerier::new(chain),
     }
 }
+
+#[test]
+fn test_storage_rollback() {
+    let mut storage = MockStorage::new();
+    storage.set(b"foo", b"bar").0.unwrap();
+
+    storage.checkpoint();
+    storage.set(b"foo", b"baz").0.unwrap();
+    storage.checkpoint();
+    storage.remove(b"foo").0.unwrap();
+    storage.set(b"new", b"value").0.unwrap();
+    storage.rollback();
+    assert_eq!(storage.get(b"foo").0.unwrap(), Some(b"baz".to_vec()));
+    assert_eq!(storage.get(b"new").0.unwrap(), None);
+    storage.rollback();
+    assert_eq!(storage.get(b"foo").0.unwrap(), Some(b"bar".to_vec()));
+}
+
+#[test]
+fn test_custom_query_fixture() {
+    let fixtures: Vec<SpecialQueryFixture> = serde_json::from_slice(
+        br#"[{"request":{"ai_oracle":{"price":{"denom":"orai"}}},"response":{"price":"3.5"}},
+            {"request":{"ai_oracle":{}},"error":"unknown query"}]"#,
+    )
+    .unwrap();
+    let query: SpecialQuery =
+        from_slice(br#"{"ai_oracle":{"price":{"denom":"orai","height":1}}}"#).unwrap();
+    assert_eq!(
+        custom_query_execute(&fixtures, &query),
+        SystemResult::Ok(ContractResult::Ok(Binary::from(br#"{"price":"3.5"}"#)))
+    );
+
+    // falls back to the next matching fixture
+    let query: SpecialQuery = from_slice(br#"{"ai_oracle":{"price":{"denom":"usdt"}}}"#).unwrap();
+    assert_eq!(
+        custom_query_execute(&fixtures, &query),
+        SystemResult::Ok(ContractResult::Err("unknown query".to_string()))
+    );
+}
+
+#[test]
+fn test_custom_msg_fixture() {
+    let fixtures: Vec<SpecialMsgFixture> = serde_json::from_slice(
+        br#"[{"msg":{"mint_tokens":{}},"effects":[{"mint":{"to":"/mint_tokens/mint_to_address",
+            "denom":"/mint_tokens/denom","amount":"/mint_tokens/amount"}}]},
+            {"msg":{"burn_tokens":{}},"effects":[{"burn":{"from":"$sender",
+            "denom":"/burn_tokens/denom","amount":"/burn_tokens/amount"}}]}]"#,
+    )
+    .unwrap();
+    let mut bank = Bank::default();
+
+    let msg: SpecialMsg = from_slice(
+        br#"{"mint_tokens":{"denom":"factory/token","amount":"100","mint_to_address":"token"}}"#,
+    )
+    .unwrap();
+    custom_msg_execute(&fixtures, &mut bank, "token", &msg).unwrap();
+    assert_eq!(
+        bank.all_balances("token"),
+        vec![Coin::new(100, "factory/token")]
+    );
+
+    // burning more than the balance fails
+    let msg: SpecialMsg =
+        from_slice(br#"{"burn_tokens":{"denom":"factory/token","amount":"101"}}"#).unwrap();
+    assert!(custom_msg_execute(&fixtures, &mut bank, "token", &msg).is_err());
+}
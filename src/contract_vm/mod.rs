@@ -1,6 +1,13 @@
+pub mod address;
 pub mod analyzer;
+pub mod bank;
 pub mod editor;
 pub mod engine;
+pub mod ibc;
 pub mod mock;
 pub mod querier;
+pub mod script;
+pub mod simulator;
+pub mod staking;
+pub mod stargate;
 pub mod watcher;
@@ -0,0 +1,698 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+
+use colored::*;
+use cosmwasm_std::{from_slice, Addr, Binary, MessageInfo, Timestamp};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::contract_vm::engine::ContractInstance;
+use crate::contract_vm::simulator::{
+    coins_to_string, parse_coins, parse_duration, AppResponse, Simulator,
+};
+
+/// Step is one command of a scenario script, it has a single action among
+/// account, contract, store, instantiate, execute, query, migrate, sudo, block and bank,
+/// a step without action only checks its expectations.
+/// Strings of a step can refer to the variables of the previous steps, like $last.data.id
+/// or ${pair.contract_address}, and to the environment, like $env.HOME, $$ is a plain $
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    // switch the sender, a name is given an address
+    pub account: Option<String>,
+    // switch the contract the calls go to, by address or alias
+    pub contract: Option<String>,
+    // store a wasm file, code ids are given in order from 1, migrate uses the last one by default
+    pub store: Option<String>,
+    // messages are written as json
+    pub instantiate: Option<Value>,
+    pub execute: Option<Value>,
+    pub query: Option<Value>,
+    pub migrate: Option<Value>,
+    pub sudo: Option<Value>,
+    // block and bank commands of the terminal, like "+1d" or "mint alice 100orai"
+    pub block: Option<String>,
+    pub bank: Option<String>,
+    // instantiate creates a new contract from the code, otherwise runs the current contract
+    pub code_id: Option<u64>,
+    // coins attached to instantiate and execute, like 100orai,5usdt
+    pub funds: Option<String>,
+    pub label: Option<String>,
+    pub admin: Option<String>,
+    // instantiate2 the code at the address derived from this text, the creator and the checksum
+    pub salt: Option<String>,
+    // variable keeping what the step produced, the previous step is always $last
+    #[serde(rename = "as")]
+    pub name: Option<String>,
+    // the query result or the response data, when it is json
+    pub expect: Option<Value>,
+    // values at paths of the result like messages.0.amount
+    pub expect_path: Option<BTreeMap<String, Value>>,
+    // the step must fail with an error containing the text
+    pub expect_error: Option<String>,
+    // attributes of the called contract
+    pub expect_attributes: Option<BTreeMap<String, String>>,
+    // events of the message tree, with at least the given attributes
+    pub expect_events: Option<Vec<ExpectedEvent>>,
+    // balances after the step, the denoms not listed are not checked,
+    // addresses can be variables
+    pub expect_balances: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ExpectedEvent {
+    #[serde(rename = "type")]
+    pub ty: String,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// Outcome is what a step produced, the expectations are checked against it
+#[derive(Default)]
+struct Outcome {
+    response: AppResponse,
+    // query result, or the response data when it is json
+    result: Option<Value>,
+}
+
+impl Step {
+    // action returns the name of the single action of the step
+    fn action(&self) -> Result<&'static str, String> {
+        let actions = [
+            ("account", self.account.is_some()),
+            ("contract", self.contract.is_some()),
+            ("store", self.store.is_some()),
+            ("instantiate", self.instantiate.is_some()),
+            ("execute", self.execute.is_some()),
+            ("query", self.query.is_some()),
+            ("migrate", self.migrate.is_some()),
+            ("sudo", self.sudo.is_some()),
+            ("block", self.block.is_some()),
+            ("bank", self.bank.is_some()),
+        ];
+        let mut actions = actions.iter().filter(|(_, set)| *set).map(|(a, _)| *a);
+        match (actions.next(), actions.next()) {
+            (Some(action), None) => Ok(action),
+            (None, _) => Ok("check"),
+            _ => Err("a step must have a single action".to_string()),
+        }
+    }
+}
+
+/// Script runs scenario steps without prompts, the calls go to the current contract
+/// from the current account like in the terminal
+pub struct Script<'a> {
+    simulator: &'a Simulator,
+    contract_addr: String,
+    sender_addr: String,
+    // last stored code
+    code_id: Option<u64>,
+    // outcomes of the named steps and of the last step
+    variables: HashMap<String, Value>,
+    passed: usize,
+    failed: usize,
+}
+
+impl<'a> Script<'a> {
+    pub fn new(simulator: &'a Simulator, contract_addr: &str, sender_addr: &str) -> Self {
+        Script {
+            simulator,
+            contract_addr: contract_addr.to_string(),
+            sender_addr: sender_addr.to_string(),
+            code_id: None,
+            variables: HashMap::new(),
+            passed: 0,
+            failed: 0,
+        }
+    }
+
+    // run runs the json steps in order and stops at the first step failing unexpectedly,
+    // then fails when any expectation was not met. Variables are replaced right before
+    // a step runs
+    pub fn run(&mut self, steps: &[Value]) -> Result<(), String> {
+        for (index, step) in steps.iter().enumerate() {
+            let step = self
+                .substitute(step)
+                .and_then(|step| serde_json::from_value::<Step>(step).map_err(|e| e.to_string()))
+                .map_err(|e| format!("step {}: {}", index + 1, e))?;
+            let action = step
+                .action()
+                .map_err(|e| format!("step {}: {}", index + 1, e))?;
+            println!(
+                "{} {} (contract {}, sender {})",
+                format!("[step {}]", index + 1).green().bold(),
+                action.blue().bold(),
+                self.contract_addr,
+                self.sender_addr
+            );
+            let mut error = None;
+            let outcome = match (self.run_step(action, &step), &step.expect_error) {
+                (Ok(outcome), None) => outcome,
+                (Err(err), None) => {
+                    return Err(format!("step {} {} failed: {}", index + 1, action, err))
+                }
+                (Err(err), Some(expected)) => {
+                    println!("{}", err.red());
+                    self.check(
+                        format!("error contains {:?}", expected),
+                        err.contains(expected.as_str()),
+                    );
+                    error = Some(err);
+                    Outcome::default()
+                }
+                (Ok(outcome), Some(expected)) => {
+                    self.check(
+                        format!("error contains {:?}, but succeeded", expected),
+                        false,
+                    );
+                    outcome
+                }
+            };
+            let Outcome { response, result } = &outcome;
+            ContractInstance::dump_events(&response.events, None);
+            if let Some(result) = result {
+                ContractInstance::dump_result("result", result.to_string().as_bytes(), 6);
+            }
+            self.check_outcome(&step, &outcome);
+
+            let variable = self.variable(&outcome, error);
+            if let Some(name) = &step.name {
+                self.variables.insert(name.to_owned(), variable.clone());
+            }
+            self.variables.insert("last".to_string(), variable);
+        }
+
+        println!(
+            "{} {} passed, {} failed",
+            "[assertions]".green().bold(),
+            self.passed.to_string().green(),
+            self.failed.to_string().red()
+        );
+        match self.failed {
+            0 => Ok(()),
+            failed => Err(format!("{} assertions failed", failed)),
+        }
+    }
+
+    // variable is what a step produced, as later steps see it
+    fn variable(&self, outcome: &Outcome, error: Option<String>) -> Value {
+        let Outcome { response, result } = outcome;
+        let attributes: BTreeMap<&str, &str> = response
+            .attributes
+            .iter()
+            .map(|attr| (attr.key.as_str(), attr.value.as_str()))
+            .collect();
+        // contracts created by the message tree, like the pair of a factory
+        let instantiated: Vec<&str> = response
+            .events
+            .iter()
+            .filter(|event| event.ty.eq("instantiate"))
+            .flat_map(|event| event.attributes.iter())
+            .filter(|attr| attr.key.eq("_contract_address"))
+            .map(|attr| attr.value.as_str())
+            .collect();
+        json!({
+            "contract_address": self.contract_addr,
+            "sender": self.sender_addr,
+            "code_id": self.code_id,
+            "data": result,
+            "attributes": attributes,
+            "events": response.events,
+            "instantiated": instantiated,
+            "gas_used": response.gas_used,
+            "error": error,
+        })
+    }
+
+    // substitute replaces the variables in the strings of a step, keys are left as they are.
+    // A string holding only a variable becomes its json value, otherwise ${variable}
+    // is written in the string and $$ is written as $
+    fn substitute(&self, value: &Value) -> Result<Value, String> {
+        match value {
+            Value::String(text) => self.substitute_text(text),
+            Value::Array(values) => values
+                .iter()
+                .map(|v| self.substitute(v))
+                .collect::<Result<Vec<Value>, String>>()
+                .map(Value::Array),
+            Value::Object(map) => map
+                .iter()
+                .map(|(k, v)| self.substitute(v).map(|v| (k.to_owned(), v)))
+                .collect::<Result<serde_json::Map<String, Value>, String>>()
+                .map(Value::Object),
+            value => Ok(value.to_owned()),
+        }
+    }
+
+    fn substitute_text(&self, text: &str) -> Result<Value, String> {
+        if let Some(reference) = text.strip_prefix('$').filter(|r| is_reference(r)) {
+            return self.lookup(reference);
+        }
+        let mut substituted = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('$') {
+            substituted.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if let Some(after) = rest.strip_prefix("$$") {
+                substituted.push('$');
+                rest = after;
+            } else if let Some(end) = rest.find('}').filter(|_| rest.starts_with("${")) {
+                match self.lookup(&rest[2..end])? {
+                    Value::String(s) => substituted.push_str(&s),
+                    value => substituted.push_str(&value.to_string()),
+                }
+                rest = &rest[end + 1..];
+            } else {
+                substituted.push('$');
+                rest = &rest[1..];
+            }
+        }
+        substituted.push_str(rest);
+        Ok(Value::String(substituted))
+    }
+
+    // lookup returns the value of a variable path like pair.contract_address or env.HOME
+    fn lookup(&self, reference: &str) -> Result<Value, String> {
+        let (name, path) = reference.split_once('.').unwrap_or((reference, ""));
+        if name.eq("env") {
+            return env::var(path)
+                .map(Value::String)
+                .map_err(|_| format!("Unknown environment variable {}", path));
+        }
+        self.variables
+            .get(name)
+            .and_then(|value| json_path(value, path))
+            .cloned()
+            .ok_or_else(|| format!("Unknown variable ${}", reference))
+    }
+
+    // check reports an assertion
+    fn check(&mut self, assertion: String, passed: bool) {
+        if passed {
+            self.passed += 1;
+            println!("{} {}", "pass".green().bold(), assertion);
+        } else {
+            self.failed += 1;
+            println!("{} {}", "fail".red().bold(), assertion);
+        }
+    }
+
+    fn check_outcome(&mut self, step: &Step, outcome: &Outcome) {
+        let Outcome { response, result } = outcome;
+        let show = |value: Option<&Value>| value.map(|v| v.to_string()).unwrap_or_default();
+
+        if let Some(expected) = &step.expect {
+            self.check(
+                format!("result is {}, got {}", expected, show(result.as_ref())),
+                result.as_ref() == Some(expected),
+            );
+        }
+        for (path, expected) in step.expect_path.iter().flatten() {
+            let value = result.as_ref().and_then(|r| json_path(r, path));
+            self.check(
+                format!("{} is {}, got {}", path, expected, show(value)),
+                value == Some(expected),
+            );
+        }
+        for (key, expected) in step.expect_attributes.iter().flatten() {
+            let value = response
+                .attributes
+                .iter()
+                .find(|attr| attr.key.eq(key))
+                .map(|attr| attr.value.as_str());
+            self.check(
+                format!(
+                    "attribute {} is {}, got {}",
+                    key,
+                    expected,
+                    value.unwrap_or_default()
+                ),
+                value == Some(expected.as_str()),
+            );
+        }
+        for expected in step.expect_events.iter().flatten() {
+            let found = response.events.iter().any(|event| {
+                event.ty.eq(&expected.ty)
+                    && expected.attributes.iter().all(|(key, value)| {
+                        event
+                            .attributes
+                            .iter()
+                            .any(|attr| attr.key.eq(key) && attr.value.eq(value))
+                    })
+            });
+            self.check(
+                format!("event {} with {:?}", expected.ty, expected.attributes),
+                found,
+            );
+        }
+        for (addr, expected) in step.expect_balances.iter().flatten() {
+            let addr = match self.substitute_text(addr) {
+                Ok(Value::String(addr)) => addr,
+                Ok(addr) => addr.to_string(),
+                Err(err) => {
+                    self.check(format!("balance of {}: {}", addr, err), false);
+                    continue;
+                }
+            };
+            let addr = self.simulator.chain().resolve(&addr);
+            let balances = self.simulator.chain().bank.all_balances(&addr);
+            let passed = match parse_coins(expected) {
+                Ok(coins) => coins
+                    .iter()
+                    .all(|coin| self.simulator.balance(&addr, &coin.denom) == coin.amount),
+                Err(_) => false,
+            };
+            self.check(
+                format!(
+                    "balance of {} is {}, got {}",
+                    addr,
+                    expected,
+                    coins_to_string(&balances)
+                ),
+                passed,
+            );
+        }
+    }
+
+    fn run_step(&mut self, action: &str, step: &Step) -> Result<Outcome, String> {
+        let simulator = self.simulator;
+        let msg = |msg: &Option<Value>| serde_json::to_vec(msg).map_err(|e| e.to_string());
+        let info = MessageInfo {
+            sender: Addr::unchecked(&self.sender_addr),
+            funds: parse_coins(step.funds.as_deref().unwrap_or_default())?,
+        };
+        let response = match action {
+            "check" => AppResponse::default(),
+            "account" => {
+                self.sender_addr = simulator.add_alias(step.account.as_deref().unwrap());
+                AppResponse::default()
+            }
+            "contract" => {
+                let contract_addr = simulator.chain().resolve(step.contract.as_deref().unwrap());
+                if !simulator.chain().engines.contains_key(&contract_addr) {
+                    return Err(format!("No such contract: {}", contract_addr));
+                }
+                self.contract_addr = contract_addr;
+                AppResponse::default()
+            }
+            "store" => {
+                let code_id = simulator.store_code(step.store.as_deref().unwrap())?;
+                println!("code id = {}", code_id.to_string().yellow());
+                self.code_id = Some(code_id);
+                AppResponse::default()
+            }
+            "instantiate" => match step.code_id {
+                // the new contract becomes the current contract
+                Some(code_id) => {
+                    let label = step.label.as_deref().unwrap_or_default();
+                    let admin = step
+                        .admin
+                        .as_deref()
+                        .map(|admin| simulator.add_alias(admin));
+                    let (contract_addr, response) = match &step.salt {
+                        Some(salt) => simulator.instantiate2(
+                            code_id,
+                            &self.sender_addr,
+                            &step.instantiate,
+                            &info.funds,
+                            label,
+                            admin,
+                            salt.as_bytes(),
+                        )?,
+                        None => simulator.instantiate(
+                            code_id,
+                            &self.sender_addr,
+                            &step.instantiate,
+                            &info.funds,
+                            label,
+                            admin,
+                        )?,
+                    };
+                    self.contract_addr = contract_addr;
+                    response
+                }
+                None if step.salt.is_some() => {
+                    return Err("instantiate with a salt needs a code id".to_string())
+                }
+                None => simulator.instantiate_raw(
+                    &self.contract_addr,
+                    &msg(&step.instantiate)?,
+                    &info,
+                )?,
+            },
+            "execute" => simulator.execute_raw(&self.contract_addr, &msg(&step.execute)?, &info)?,
+            "query" => {
+                let data = simulator.query_raw(&self.contract_addr, &msg(&step.query)?)?;
+                AppResponse {
+                    data: Some(data),
+                    ..AppResponse::default()
+                }
+            }
+            "migrate" => {
+                let code_id = match step.code_id.or(self.code_id) {
+                    None => return Err("migrate needs a code id".to_string()),
+                    Some(code_id) => code_id,
+                };
+                simulator.migrate_raw(
+                    &self.contract_addr,
+                    &self.sender_addr,
+                    code_id,
+                    &msg(&step.migrate)?,
+                )?
+            }
+            "sudo" => simulator.sudo_raw(&self.contract_addr, &msg(&step.sudo)?)?,
+            "block" => {
+                block_command(simulator, step.block.as_deref().unwrap())?;
+                AppResponse::default()
+            }
+            _ => {
+                bank_command(simulator, step.bank.as_deref().unwrap())?;
+                AppResponse::default()
+            }
+        };
+        let result = response.data.as_ref().map(json_result);
+        Ok(Outcome { response, result })
+    }
+}
+
+// is_reference tells whether a string is a variable path, like pair.data.0.id
+fn is_reference(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+}
+
+// json_result reads the data of a result as json, other data is kept as base64
+fn json_result(data: &Binary) -> Value {
+    from_slice(data.as_slice()).unwrap_or_else(|_| Value::String(data.to_base64()))
+}
+
+// json_path returns the value at a dotted path like messages.0.amount
+fn json_path<'v>(value: &'v Value, path: &str) -> Option<&'v Value> {
+    if path.is_empty() {
+        return Some(value);
+    }
+    value.pointer(&format!("/{}", path.replace('.', "/")))
+}
+
+// block_command changes the block of the chain, moving forward ends every block on the way:
+// commit, auto on|off, height N, time SECONDS, chain-id ID, +N blocks or +DURATION like +1d
+pub fn block_command(simulator: &Simulator, command: &str) -> Result<(), String> {
+    match command.split_whitespace().collect::<Vec<&str>>().as_slice() {
+        ["commit"] => {
+            simulator.end_block();
+            Ok(())
+        }
+        ["auto", "on"] => {
+            simulator.chain_mut().auto_commit = true;
+            Ok(())
+        }
+        ["auto", "off"] => {
+            simulator.chain_mut().auto_commit = false;
+            Ok(())
+        }
+        ["height", height] => match height.parse::<u64>() {
+            Ok(height) => simulator.set_height(height),
+            Err(_) => Err(format!("Wrong height {}", height)),
+        },
+        ["time", time] => time
+            .parse::<u64>()
+            .map(|time| simulator.set_time(Timestamp::from_seconds(time)))
+            .map_err(|_| format!("Wrong time {}", time)),
+        ["chain-id", chain_id] => {
+            simulator.set_chain_id(chain_id);
+            Ok(())
+        }
+        [advance] if advance.starts_with('+') => match advance[1..].parse::<u64>() {
+            Ok(blocks) => simulator.advance_blocks(blocks),
+            Err(_) => {
+                parse_duration(&advance[1..]).and_then(|seconds| simulator.advance_time(seconds))
+            }
+        },
+        _ => Err(format!("Wrong block command {}", command)),
+    }
+}
+
+// bank_command manages the accounts, coins are written like 100orai,5usdt or 100orai 5usdt:
+// accounts, add ADDR [COINS], mint ADDR COINS, burn ADDR COINS or set ADDR COINS,
+// a new name is given an address
+pub fn bank_command(simulator: &Simulator, command: &str) -> Result<(), String> {
+    match command.split_whitespace().collect::<Vec<&str>>().as_slice() {
+        ["accounts"] => Ok(()),
+        ["add", alias, coins @ ..] => parse_coins(&coins.join(",")).map(|coins| {
+            let addr = simulator.add_alias(alias);
+            simulator.add_account(&addr, &coins);
+        }),
+        ["mint", addr, coins @ ..] if !coins.is_empty() => {
+            let addr = simulator.add_alias(addr);
+            parse_coins(&coins.join(",")).map(|coins| simulator.mint(&addr, &coins))
+        }
+        ["burn", addr, coins @ ..] if !coins.is_empty() => {
+            let addr = simulator.add_alias(addr);
+            parse_coins(&coins.join(",")).and_then(|coins| simulator.burn(&addr, &coins))
+        }
+        ["set", addr, coins @ ..] => {
+            let addr = simulator.add_alias(addr);
+            parse_coins(&coins.join(",")).map(|coins| simulator.set_balance(&addr, &coins))
+        }
+        _ => Err(format!("Wrong bank command {}", command)),
+    }
+}
+
+#[test]
+fn test_script() {
+    use cosmwasm_std::{Coin, Uint128};
+
+    let simulator = Simulator::new();
+    let steps: Vec<Value> = serde_json::from_str(
+        r#"[
+            { "bank": "add alice 100orai" },
+            { "account": "alice" },
+            { "bank": "burn alice 40orai" },
+            { "block": "+1d" }
+        ]"#,
+    )
+    .unwrap();
+    let mut script = Script::new(&simulator, "", "");
+    script.run(&steps).unwrap();
+
+    let alice = simulator.add_alias("alice");
+    assert_eq!(script.sender_addr, alice);
+    assert_eq!(simulator.balance(&alice, "orai"), Uint128::from(60u64));
+
+    // the run stops at the first failing step
+    let steps: Vec<Value> = serde_json::from_str(
+        r#"[
+            { "bank": "burn alice 100orai" },
+            { "bank": "mint alice 1orai" }
+        ]"#,
+    )
+    .unwrap();
+    let err = script.run(&steps).unwrap_err();
+    assert!(err.starts_with("step 1 bank failed"));
+    assert_eq!(
+        simulator.chain().bank.all_balances(&alice),
+        vec![Coin::new(60, "orai")]
+    );
+
+    // steps have a single action
+    let step = json!({ "account": "bob", "bank": "accounts" });
+    assert!(Script::new(&simulator, "", "").run(&[step]).is_err());
+    // instantiate2 needs the code to derive the address from
+    let step = json!({ "instantiate": {}, "salt": "pair" });
+    let err = Script::new(&simulator, "", "").run(&[step]).unwrap_err();
+    assert!(err.ends_with("instantiate with a salt needs a code id"));
+
+    // coins can be separated by spaces as well
+    bank_command(&simulator, "mint alice 1orai 2usdt").unwrap();
+    assert_eq!(
+        simulator.chain().bank.all_balances(&alice),
+        vec![Coin::new(61, "orai"), Coin::new(2, "usdt")]
+    );
+
+    // a new name is given an address, like in add
+    bank_command(&simulator, "mint carol 1orai").unwrap();
+    let carol = simulator.add_alias("carol");
+    assert_eq!(simulator.balance(&carol, "orai"), Uint128::from(1u64));
+}
+
+#[test]
+fn test_script_assertions() {
+    let simulator = Simulator::new();
+    let steps: Vec<Value> = serde_json::from_str(
+        r#"[
+            { "bank": "add alice 100orai,5usdt", "expect_balances": { "alice": "100orai" } },
+            { "bank": "burn alice 200orai", "expect_error": "insufficient funds" },
+            { "expect_balances": { "alice": "100orai,5usdt" } }
+        ]"#,
+    )
+    .unwrap();
+    let mut script = Script::new(&simulator, "", "");
+    assert_eq!(script.run(&steps), Ok(()));
+    assert_eq!((script.passed, script.failed), (3, 0));
+
+    // failed expectations do not stop the script, they fail it at the end
+    let steps: Vec<Value> = serde_json::from_str(
+        r#"[
+            { "bank": "mint alice 1orai", "expect_error": "insufficient funds" },
+            { "expect_balances": { "alice": "100orai" } },
+            { "bank": "burn alice 1orai", "expect_balances": { "alice": "100orai" } }
+        ]"#,
+    )
+    .unwrap();
+    let mut script = Script::new(&simulator, "", "");
+    assert!(script.run(&steps).is_err());
+    assert_eq!((script.passed, script.failed), (1, 2));
+
+    let result = json!({ "messages": [{ "amount": "10" }] });
+    assert_eq!(json_path(&result, "messages.0.amount"), Some(&json!("10")));
+    assert_eq!(json_path(&result, "messages.1"), None);
+    assert_eq!(
+        json_result(&Binary::from(b"{\"id\":1}")),
+        json!({ "id": 1 })
+    );
+}
+
+#[test]
+fn test_script_variables() {
+    use cosmwasm_std::Uint128;
+
+    let simulator = Simulator::new();
+    env::set_var("SCRIPT_TEST_AMOUNT", "7");
+    let steps: Vec<Value> = serde_json::from_str(
+        r#"[
+            { "account": "alice", "as": "alice" },
+            { "bank": "add ${alice.sender} ${env.SCRIPT_TEST_AMOUNT}orai" },
+            { "account": "bob" },
+            { "bank": "set ${last.sender} 1orai" },
+            { "account": "$alice.sender", "expect_balances": { "$last.sender": "1orai" } }
+        ]"#,
+    )
+    .unwrap();
+    let mut script = Script::new(&simulator, "", "");
+    assert_eq!(script.run(&steps), Ok(()));
+
+    let alice = simulator.add_alias("alice");
+    assert_eq!(script.sender_addr, alice);
+    assert_eq!(simulator.balance(&alice, "orai"), Uint128::from(7u64));
+    assert_eq!(script.lookup("alice.sender"), Ok(json!(alice)));
+    assert_eq!(script.lookup("last.code_id"), Ok(Value::Null));
+
+    // a whole string variable keeps its json type
+    let value =
+        script.substitute(&json!({ "gas": "$last.gas_used", "text": "id ${last.gas_used}" }));
+    assert_eq!(value, Ok(json!({ "gas": 0, "text": "id 0" })));
+    assert!(script.substitute(&json!("$missing.data")).is_err());
+    assert_eq!(script.substitute(&json!("$5")), Ok(json!("$5")));
+
+    // $$ writes a plain $, keys are never replaced
+    let value =
+        script.substitute(&json!({ "$denom": "$$ORAI", "memo": "pay $$5 to ${last.sender}" }));
+    assert_eq!(
+        value,
+        Ok(json!({ "$denom": "$ORAI", "memo": format!("pay $5 to {}", script.sender_addr) }))
+    );
+}
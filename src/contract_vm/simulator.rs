@@ -0,0 +1,2082 @@
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use colored::*;
+use cosmwasm_std::{
+    from_slice, to_vec, Addr, Attribute, BankMsg, Binary, BlockInfo, Coin, ContractInfo, CosmosMsg,
+    DistributionMsg, Env, Event, IbcAcknowledgement, IbcChannelCloseMsg, IbcChannelConnectMsg,
+    IbcChannelOpenMsg, IbcMsg, IbcOrder, IbcPacketAckMsg, IbcPacketReceiveMsg, IbcPacketTimeoutMsg,
+    MessageInfo, Reply, ReplyOn, Response, StakingMsg, SubMsg, SubMsgResponse, SubMsgResult,
+    Timestamp, TransactionInfo, Uint128, Uint64, WasmMsg,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::contract_vm::address::{
+    alias_address, canonicalize, check_prefix, contract_address, is_address, predictable_address,
+    Bech32Api, BECH32_PREFIX,
+};
+use crate::contract_vm::analyzer::Analyzer;
+use crate::contract_vm::bank::Bank;
+use crate::contract_vm::engine::{
+    ContractInstance, BLOCK_HEIGHT, BLOCK_INTERVAL, BLOCK_TIME, CHAIN_ID, DENOM,
+};
+use crate::contract_vm::ibc::{is_timed_out, relayer_addr, Relayer};
+use crate::contract_vm::mock::{
+    custom_msg_execute, MockStorage, SpecialMsg, SpecialMsgFixture, SpecialQueryFixture,
+};
+use crate::contract_vm::staking::StakingQuerier;
+use crate::contract_vm::stargate::Stargate;
+
+/// ContractMeta is the metadata of an instantiated contract, like wasmd ContractInfo
+#[derive(Clone)]
+pub struct ContractMeta {
+    pub code_id: u64,
+    // address that instantiated the contract
+    pub creator: String,
+    pub admin: Option<String>,
+    pub label: String,
+    // port of the contract once it opened a channel
+    pub ibc_port: Option<String>,
+}
+
+/// AppResponse is the result of a call, the events cover the whole message tree
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AppResponse {
+    // attributes returned by the called contract
+    pub attributes: Vec<Attribute>,
+    pub events: Vec<Event>,
+    pub data: Option<Binary>,
+    // gas used by the called contract, the contracts it calls have their own meters
+    pub gas_used: u64,
+}
+
+impl AppResponse {
+    fn new(contract_addr: &str, response: SubMsgResponse, gas_used: u64) -> Self {
+        let attributes = response
+            .events
+            .iter()
+            .find(|event| {
+                event.ty.eq("wasm")
+                    && event.attributes.iter().any(|attr| {
+                        attr.key.eq("_contract_address") && attr.value.eq(contract_addr)
+                    })
+            })
+            .map(|event| {
+                event
+                    .attributes
+                    .iter()
+                    .filter(|attr| attr.key.ne("_contract_address"))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        AppResponse {
+            attributes,
+            events: response.events,
+            data: response.data,
+            gas_used,
+        }
+    }
+}
+
+// Transaction keeps what is needed to undo the changes made after it began,
+// contract storages keep their own undo log since the checkpoint
+struct Transaction {
+    aliases: HashMap<String, String>,
+    bank: Bank,
+    contracts: HashMap<String, ContractMeta>,
+    instance_id: u64,
+    relayer: Relayer,
+    staking: StakingQuerier,
+}
+
+/// Chain is the state of the simulated chain, contracts read it back through their querier
+pub struct Chain {
+    // a contract is borrowed while it runs, so it can not be entered again
+    pub engines: HashMap<String, Rc<RefCell<ContractInstance>>>,
+    // known accounts, their balances are kept in the bank
+    pub accounts: Vec<String>,
+    // bech32 prefix of the addresses, contracts reject any other
+    pub bech32_prefix: &'static str,
+    // names standing for addresses, like alice or the folder of a contract
+    pub aliases: HashMap<String, String>,
+    pub bank: Bank,
+    pub block: BlockInfo,
+    // seconds added to the block time by every block
+    pub block_interval: u64,
+    // every call from the user ends its block, otherwise calls pile up until end_block
+    pub auto_commit: bool,
+    // contract storages print their changes
+    pub log_storage: bool,
+    // transactions delivered in the current block, the next one gets this index
+    pub tx_index: u32,
+    // code registry, code id is index + 1
+    pub codes: Vec<String>,
+    pub contracts: HashMap<String, ContractMeta>,
+    // sequence of the contracts with a classic address, like the wasmd instance id
+    pub instance_id: u64,
+    transactions: Vec<Transaction>,
+    pub relayer: Relayer,
+    pub staking: StakingQuerier,
+    pub special_queries: Vec<SpecialQueryFixture>,
+    pub special_msgs: Vec<SpecialMsgFixture>,
+    pub stargate: Stargate,
+}
+
+impl Default for Chain {
+    fn default() -> Self {
+        Chain::new()
+    }
+}
+
+impl Chain {
+    pub fn new() -> Self {
+        Chain {
+            engines: HashMap::new(),
+            accounts: Vec::new(),
+            bech32_prefix: BECH32_PREFIX,
+            aliases: HashMap::new(),
+            bank: Bank::default(),
+            block: BlockInfo {
+                height: BLOCK_HEIGHT,
+                time: Timestamp::from_seconds(BLOCK_TIME),
+                chain_id: CHAIN_ID.to_string(),
+            },
+            block_interval: BLOCK_INTERVAL,
+            auto_commit: true,
+            log_storage: false,
+            tx_index: 0,
+            codes: Vec::new(),
+            contracts: HashMap::new(),
+            instance_id: 0,
+            transactions: Vec::new(),
+            relayer: Relayer::default(),
+            staking: StakingQuerier::new(DENOM, BLOCK_HEIGHT, &[], &[]),
+            special_queries: Vec::new(),
+            special_msgs: Vec::new(),
+            stargate: new_stargate(),
+        }
+    }
+
+    // set_bech32_prefix changes the prefix of the addresses, before any is made
+    pub fn set_bech32_prefix(&mut self, prefix: &str) -> Result<(), String> {
+        check_prefix(prefix)?;
+        // contracts keep a copy of the api, which must be Copy
+        self.bech32_prefix = Box::leak(prefix.to_string().into_boxed_str());
+        Ok(())
+    }
+
+    // add_alias returns the address standing for a name, deriving it the first time,
+    // a name that is already an address stands for itself
+    pub fn add_alias(&mut self, alias: &str) -> String {
+        if is_address(self.bech32_prefix, alias) {
+            return alias.to_string();
+        }
+        let prefix = self.bech32_prefix;
+        self.aliases
+            .entry(alias.to_string())
+            .or_insert_with(|| alias_address(prefix, alias))
+            .to_owned()
+    }
+
+    // resolve returns the address of an alias, anything else is returned as is
+    pub fn resolve(&self, name: &str) -> String {
+        match self.aliases.get(name) {
+            Some(addr) => addr.to_owned(),
+            None => name.to_string(),
+        }
+    }
+
+    // next_contract_address returns the address of the next contract like wasmd,
+    // from the code id and the instance sequence
+    pub fn next_contract_address(&mut self, code_id: u64) -> String {
+        self.instance_id += 1;
+        contract_address(self.bech32_prefix, code_id, self.instance_id)
+    }
+
+    pub fn alias(&self, addr: &str) -> Option<&str> {
+        self.aliases
+            .iter()
+            .find(|(_, a)| a.as_str().eq(addr))
+            .map(|(alias, _)| alias.as_str())
+    }
+}
+
+// with_engine runs a contract at the current block, the chain is not borrowed meanwhile
+// so the contract can query it, a contract already running is an error.
+// Inside a transaction the env carries its index in the block
+pub fn with_engine<T>(
+    chain: &RefCell<Chain>,
+    contract_addr: &str,
+    f: impl FnOnce(&mut ContractInstance, &Env) -> Result<T, String>,
+) -> Result<T, String> {
+    let (engine, env) = {
+        let chain = chain.borrow();
+        let engine = match chain.engines.get(contract_addr) {
+            None => return Err(format!("No such contract: {}", contract_addr)),
+            Some(engine) => engine.clone(),
+        };
+        let env = Env {
+            block: chain.block.clone(),
+            contract: ContractInfo {
+                address: Addr::unchecked(contract_addr),
+            },
+            transaction: if chain.transactions.is_empty() {
+                None
+            } else {
+                Some(TransactionInfo {
+                    index: chain.tx_index,
+                })
+            },
+        };
+        (engine, env)
+    };
+    let mut engine = borrow_engine(&engine, contract_addr)?;
+    f(&mut engine, &env)
+}
+
+// borrow_engine borrows a contract to run it, the contract being borrowed already means
+// it is running, like when it queries itself
+fn borrow_engine<'a, T>(
+    engine: &'a RefCell<T>,
+    contract_addr: &str,
+) -> Result<RefMut<'a, T>, String> {
+    engine
+        .try_borrow_mut()
+        .map_err(|_| format!("Contract {} is already running", contract_addr))
+}
+
+/// Simulator owns the simulated chain and runs the calls made to its contracts,
+/// messages of the responses are dispatched as sub transactions
+pub struct Simulator {
+    chain: Rc<RefCell<Chain>>,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Simulator::new()
+    }
+}
+
+impl Simulator {
+    pub fn new() -> Self {
+        Simulator {
+            chain: Rc::new(RefCell::new(Chain::new())),
+        }
+    }
+
+    // chain must not be held while contracts run, they borrow it to answer their queries
+    pub fn chain(&self) -> Ref<'_, Chain> {
+        self.chain.borrow()
+    }
+
+    pub fn chain_mut(&self) -> RefMut<'_, Chain> {
+        self.chain.borrow_mut()
+    }
+
+    pub fn with_engine<T>(
+        &self,
+        contract_addr: &str,
+        f: impl FnOnce(&mut ContractInstance, &Env) -> Result<T, String>,
+    ) -> Result<T, String> {
+        with_engine(&self.chain, contract_addr, f)
+    }
+
+    pub fn analyzer(&self, contract_addr: &str) -> Result<Rc<Analyzer>, String> {
+        self.with_engine(contract_addr, |engine, _| Ok(engine.analyzer.clone()))
+    }
+
+    pub fn gas_left(&self, contract_addr: &str) -> u64 {
+        self.with_engine(contract_addr, |engine, _| {
+            Ok(engine.instance.get_gas_left())
+        })
+        .unwrap_or_default()
+    }
+
+    // gas_meter returns the instance of a contract with the gas it used since it was compiled
+    fn gas_meter(&self, contract_addr: &str) -> Option<(Rc<RefCell<ContractInstance>>, u64)> {
+        let engine = self.chain.borrow().engines.get(contract_addr)?.clone();
+        let gas_used = engine.borrow().gas_used();
+        Some((engine, gas_used))
+    }
+
+    // gas_used_since returns the gas a contract used since its meter was read,
+    // an instance compiled meanwhile, like a migrated one, counts from zero
+    fn gas_used_since(
+        &self,
+        contract_addr: &str,
+        meter: Option<(Rc<RefCell<ContractInstance>>, u64)>,
+    ) -> u64 {
+        match (meter, self.gas_meter(contract_addr)) {
+            (Some((before, gas_before)), Some((after, gas_after)))
+                if Rc::ptr_eq(&before, &after) =>
+            {
+                gas_after.saturating_sub(gas_before)
+            }
+            (_, Some((_, gas_after))) => gas_after,
+            _ => 0,
+        }
+    }
+
+    // store_code returns the code id of a wasm file, registering it when it is new
+    pub fn store_code(&self, wasm_file: &str) -> Result<u64, String> {
+        if !Path::new(wasm_file).is_file() {
+            return Err(format!("No such wasm file: {}", wasm_file));
+        }
+        let Chain { codes, .. } = &mut *self.chain.borrow_mut();
+        match codes.iter().position(|f| f.eq(wasm_file)) {
+            Some(index) => Ok((index + 1) as u64),
+            None => {
+                codes.push(wasm_file.to_string());
+                Ok(codes.len() as u64)
+            }
+        }
+    }
+
+    fn code_file(&self, code_id: u64) -> Result<String, String> {
+        let Chain { codes, .. } = &*self.chain.borrow();
+        match code_id
+            .checked_sub(1)
+            .and_then(|index| codes.get(index as usize))
+        {
+            None => Err(format!("No such code: {}", code_id)),
+            Some(file) => Ok(file.to_owned()),
+        }
+    }
+
+    // checksum returns the hash of the wasm code, part of the instantiate2 addresses
+    pub fn checksum(&self, code_id: u64) -> Result<Vec<u8>, String> {
+        let wasm_file = self.code_file(code_id)?;
+        let wasm = fs::read(&wasm_file).map_err(|e| format!("{}: {}", wasm_file, e))?;
+        Ok(Sha256::digest(&wasm).to_vec())
+    }
+
+    fn compile(&self, wasm_file: &str, storage: &MockStorage) -> Result<ContractInstance, String> {
+        let (api, storage) = {
+            let chain = self.chain.borrow();
+            let mut storage = storage.to_owned();
+            storage.log = chain.log_storage;
+            (Bech32Api::new(chain.bech32_prefix), storage)
+        };
+        ContractInstance::new_instance(wasm_file, api, Rc::downgrade(&self.chain), &storage)
+    }
+
+    // load_contract stores the code of a wasm file and runs it at the next contract address,
+    // created by the creator, the name stands for the address which is returned
+    pub fn load_contract(
+        &self,
+        wasm_file: &str,
+        name: &str,
+        creator: &str,
+    ) -> Result<String, String> {
+        let engine = self.compile(wasm_file, &MockStorage::default())?;
+        let code_id = self.store_code(wasm_file)?;
+        let chain = &mut *self.chain.borrow_mut();
+        let contract_addr = chain.next_contract_address(code_id);
+        chain
+            .aliases
+            .insert(name.to_string(), contract_addr.to_owned());
+        chain.contracts.insert(
+            contract_addr.to_owned(),
+            ContractMeta {
+                code_id,
+                creator: creator.to_string(),
+                admin: None,
+                label: name.to_owned(),
+                ibc_port: None,
+            },
+        );
+        chain
+            .engines
+            .insert(contract_addr.to_owned(), Rc::new(RefCell::new(engine)));
+        Ok(contract_addr)
+    }
+
+    // reload_contract compiles the wasm file again over the storage of the contract,
+    // the contract can be given by its alias
+    pub fn reload_contract(&self, wasm_file: &str, contract_addr: &str) -> Result<(), String> {
+        let contract_addr = &self.chain.borrow().resolve(contract_addr);
+        let storage = self.with_engine(contract_addr, |engine, _| engine.storage())?;
+        let engine = self.compile(wasm_file, &storage)?;
+        self.chain
+            .borrow_mut()
+            .engines
+            .insert(contract_addr.to_owned(), Rc::new(RefCell::new(engine)));
+        Ok(())
+    }
+
+    // replace_code compiles a stored code over the current storage of the contract
+    fn replace_code(&self, contract_addr: &str, code_id: u64) -> Result<(), String> {
+        let wasm_file = self.code_file(code_id)?;
+        self.reload_contract(&wasm_file, contract_addr)
+    }
+
+    // transaction runs f as a transaction, every change made by the message tree
+    // is discarded when any message fails
+    pub fn transaction<T>(
+        &self,
+        f: impl FnOnce(&Simulator) -> Result<T, String>,
+    ) -> Result<T, String> {
+        self.begin_transaction();
+        match f(self) {
+            Ok(result) => {
+                self.commit_transaction();
+                Ok(result)
+            }
+            Err(err) => {
+                self.rollback_transaction();
+                Err(err)
+            }
+        }
+    }
+
+    // deliver_tx runs a call from the user as the next transaction of the current block,
+    // a failed transaction is dropped from the block
+    pub fn deliver_tx<T>(
+        &self,
+        f: impl FnOnce(&Simulator) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let result = self.transaction(f)?;
+        let auto_commit = {
+            let mut chain = self.chain.borrow_mut();
+            chain.tx_index += 1;
+            chain.auto_commit
+        };
+        if auto_commit {
+            self.end_block();
+        }
+        Ok(result)
+    }
+
+    // end_block commits the current block and moves every contract to the next one,
+    // paying back the undelegations that are over
+    pub fn end_block(&self) {
+        let Chain {
+            bank,
+            staking,
+            block,
+            block_interval,
+            tx_index,
+            ..
+        } = &mut *self.chain.borrow_mut();
+        *tx_index = 0;
+        block.height += 1;
+        block.time = block.time.plus_seconds(*block_interval);
+        staking.set_height(block.height);
+        staking.complete_unbonding(bank);
+    }
+
+    // set_height moves the chain to another height, going forward moves the time by the
+    // blocks skipped and pays back the undelegations that are over meanwhile
+    pub fn set_height(&self, height: u64) -> Result<(), String> {
+        let Chain {
+            bank,
+            staking,
+            block,
+            block_interval,
+            ..
+        } = &mut *self.chain.borrow_mut();
+        if height > block.height {
+            let nanos = (height - block.height)
+                .checked_mul(*block_interval)
+                .and_then(|seconds| seconds.checked_mul(1_000_000_000))
+                .and_then(|nanos| nanos.checked_add(block.time.nanos()))
+                .ok_or_else(|| format!("Height {} is too far ahead", height))?;
+            block.time = Timestamp::from_nanos(nanos);
+        }
+        block.height = height;
+        staking.set_height(height);
+        staking.complete_unbonding(bank);
+        Ok(())
+    }
+
+    pub fn set_time(&self, time: Timestamp) {
+        self.chain.borrow_mut().block.time = time;
+    }
+
+    pub fn set_chain_id(&self, chain_id: &str) {
+        self.chain.borrow_mut().block.chain_id = chain_id.to_string();
+    }
+
+    // set_log_storage prints the storage changes of every contract, off by default
+    pub fn set_log_storage(&self, log: bool) {
+        let Chain {
+            engines,
+            log_storage,
+            ..
+        } = &mut *self.chain.borrow_mut();
+        *log_storage = log;
+        for engine in engines.values() {
+            engine
+                .borrow_mut()
+                .instance
+                .with_storage(|storage| {
+                    storage.log = log;
+                    Ok(())
+                })
+                .unwrap();
+        }
+    }
+
+    // advance_blocks ends as many blocks without any call at once, to wait for expirations
+    // or unbondings, rewards accrue over the whole span
+    pub fn advance_blocks(&self, blocks: u64) -> Result<(), String> {
+        if blocks == 0 {
+            return Ok(());
+        }
+        let height = self.chain.borrow().block.height.checked_add(blocks);
+        match height {
+            None => return Err(format!("Can not advance {} blocks", blocks)),
+            Some(height) => self.set_height(height)?,
+        }
+        self.chain.borrow_mut().tx_index = 0;
+        Ok(())
+    }
+
+    // advance_time moves the chain forward by a duration, ending the blocks produced meanwhile
+    pub fn advance_time(&self, seconds: u64) -> Result<(), String> {
+        let block_interval = self.chain.borrow().block_interval;
+        let blocks = seconds.checked_div(block_interval).unwrap_or_default();
+        self.advance_blocks(blocks)?;
+        let block = &mut self.chain.borrow_mut().block;
+        let nanos = (seconds - blocks * block_interval)
+            .checked_mul(1_000_000_000)
+            .and_then(|nanos| nanos.checked_add(block.time.nanos()))
+            .ok_or_else(|| format!("Can not advance {} seconds", seconds))?;
+        block.time = Timestamp::from_nanos(nanos);
+        Ok(())
+    }
+
+    pub fn set_balance(&self, addr: &str, balance: &[Coin]) {
+        self.chain
+            .borrow_mut()
+            .bank
+            .set_balance(addr, balance.to_vec());
+    }
+
+    // add_account makes an address known to the terminal, minting its initial balance
+    pub fn add_account(&self, addr: &str, balance: &[Coin]) {
+        let Chain { accounts, bank, .. } = &mut *self.chain.borrow_mut();
+        if !accounts.iter().any(|account| account.eq(addr)) {
+            accounts.push(addr.to_string());
+            accounts.sort();
+        }
+        bank.mint(addr, balance);
+    }
+
+    pub fn add_alias(&self, alias: &str) -> String {
+        self.chain.borrow_mut().add_alias(alias)
+    }
+
+    pub fn mint(&self, addr: &str, amount: &[Coin]) {
+        self.chain.borrow_mut().bank.mint(addr, amount);
+    }
+
+    pub fn burn(&self, addr: &str, amount: &[Coin]) -> Result<(), String> {
+        self.chain.borrow_mut().bank.burn(addr, amount)
+    }
+
+    pub fn balance(&self, addr: &str, denom: &str) -> Uint128 {
+        self.chain.borrow().bank.balance(addr, denom)
+    }
+
+    // run_call runs a call to a contract as a transaction, reporting the gas used by the contract
+    fn run_call(
+        &self,
+        contract_addr: &str,
+        f: impl FnOnce(&Simulator) -> Result<SubMsgResponse, String>,
+    ) -> Result<AppResponse, String> {
+        let meter = self.gas_meter(contract_addr);
+        let response = self.deliver_tx(f)?;
+        let gas_used = self.gas_used_since(contract_addr, meter);
+        Ok(AppResponse::new(contract_addr, response, gas_used))
+    }
+
+    // instantiate creates a contract from a stored code and returns its address,
+    // the sender becomes creator of the contract
+    pub fn instantiate<T: Serialize + ?Sized>(
+        &self,
+        code_id: u64,
+        sender_addr: &str,
+        msg: &T,
+        funds: &[Coin],
+        label: &str,
+        admin: Option<String>,
+    ) -> Result<(String, AppResponse), String> {
+        let msg = to_vec(msg).map_err(|e| e.to_string())?;
+        let info = MessageInfo {
+            sender: Addr::unchecked(sender_addr),
+            funds: funds.to_vec(),
+        };
+        self.instantiate_call(&info, code_id, &msg, label, admin, None)
+    }
+
+    // instantiate2 creates a contract at an address predictable from the code checksum,
+    // the sender and the salt
+    #[allow(clippy::too_many_arguments)]
+    pub fn instantiate2<T: Serialize + ?Sized>(
+        &self,
+        code_id: u64,
+        sender_addr: &str,
+        msg: &T,
+        funds: &[Coin],
+        label: &str,
+        admin: Option<String>,
+        salt: &[u8],
+    ) -> Result<(String, AppResponse), String> {
+        let msg = to_vec(msg).map_err(|e| e.to_string())?;
+        let info = MessageInfo {
+            sender: Addr::unchecked(sender_addr),
+            funds: funds.to_vec(),
+        };
+        self.instantiate_call(&info, code_id, &msg, label, admin, Some((salt, false)))
+    }
+
+    fn instantiate_call(
+        &self,
+        info: &MessageInfo,
+        code_id: u64,
+        msg: &[u8],
+        label: &str,
+        admin: Option<String>,
+        salt: Option<(&[u8], bool)>,
+    ) -> Result<(String, AppResponse), String> {
+        let (contract_addr, response) = self
+            .deliver_tx(|sim| sim.instantiate_contract(info, code_id, msg, label, admin, salt))?;
+        let gas_used = self.gas_used_since(&contract_addr, None);
+        let response = AppResponse::new(&contract_addr, response, gas_used);
+        Ok((contract_addr, response))
+    }
+
+    pub fn execute<T: Serialize + ?Sized>(
+        &self,
+        contract_addr: &str,
+        sender_addr: &str,
+        msg: &T,
+        funds: &[Coin],
+    ) -> Result<AppResponse, String> {
+        let msg = to_vec(msg).map_err(|e| e.to_string())?;
+        let info = MessageInfo {
+            sender: Addr::unchecked(sender_addr),
+            funds: funds.to_vec(),
+        };
+        self.execute_raw(contract_addr, &msg, &info)
+    }
+
+    pub fn migrate<T: Serialize + ?Sized>(
+        &self,
+        contract_addr: &str,
+        sender_addr: &str,
+        code_id: u64,
+        msg: &T,
+    ) -> Result<AppResponse, String> {
+        let msg = to_vec(msg).map_err(|e| e.to_string())?;
+        self.migrate_raw(contract_addr, sender_addr, code_id, &msg)
+    }
+
+    pub fn sudo<T: Serialize + ?Sized>(
+        &self,
+        contract_addr: &str,
+        msg: &T,
+    ) -> Result<AppResponse, String> {
+        let msg = to_vec(msg).map_err(|e| e.to_string())?;
+        self.sudo_raw(contract_addr, &msg)
+    }
+
+    pub fn query<T: Serialize + ?Sized, R: DeserializeOwned>(
+        &self,
+        contract_addr: &str,
+        msg: &T,
+    ) -> Result<R, String> {
+        let msg = to_vec(msg).map_err(|e| e.to_string())?;
+        let data = self.query_raw(contract_addr, &msg)?;
+        from_slice(data.as_slice()).map_err(|e| e.to_string())
+    }
+
+    // instantiate_raw runs the instantiate entry point of a loaded contract,
+    // the sender becomes creator and admin of the contract
+    pub fn instantiate_raw(
+        &self,
+        contract_addr: &str,
+        msg: &[u8],
+        info: &MessageInfo,
+    ) -> Result<AppResponse, String> {
+        self.run_call(contract_addr, |sim| {
+            let response = sim.run_instantiate(contract_addr, msg, info)?;
+            if let Some(meta) = sim.chain.borrow_mut().contracts.get_mut(contract_addr) {
+                meta.creator = info.sender.to_string();
+                meta.admin = Some(info.sender.to_string());
+            }
+            Ok(response)
+        })
+    }
+
+    pub fn execute_raw(
+        &self,
+        contract_addr: &str,
+        msg: &[u8],
+        info: &MessageInfo,
+    ) -> Result<AppResponse, String> {
+        self.run_call(contract_addr, |sim| {
+            sim.run_execute(contract_addr, msg, info)
+        })
+    }
+
+    // migrate_raw moves the contract to another code then runs its migrate entry point,
+    // only the admin can migrate
+    pub fn migrate_raw(
+        &self,
+        contract_addr: &str,
+        sender_addr: &str,
+        code_id: u64,
+        msg: &[u8],
+    ) -> Result<AppResponse, String> {
+        self.run_call(contract_addr, |sim| {
+            sim.migrate_contract(contract_addr, sender_addr, code_id)?;
+            sim.run_migrate(contract_addr, msg)
+        })
+    }
+
+    pub fn sudo_raw(&self, contract_addr: &str, msg: &[u8]) -> Result<AppResponse, String> {
+        self.run_call(contract_addr, |sim| {
+            let response =
+                sim.with_engine(contract_addr, |engine, env| engine.run_sudo(env, msg))?;
+            sim.handle_response(contract_addr, response)
+        })
+    }
+
+    pub fn query_raw(&self, contract_addr: &str, msg: &[u8]) -> Result<Binary, String> {
+        self.with_engine(contract_addr, |engine, env| engine.run_query(env, msg))
+    }
+
+    // send_funds moves the funds attached to a call into the contract,
+    // the caller transaction gives them back when the call fails
+    fn send_funds(&self, contract_addr: &str, info: &MessageInfo) -> Result<(), String> {
+        if info.funds.is_empty() {
+            return Ok(());
+        }
+        self.chain
+            .borrow_mut()
+            .bank
+            .send(info.sender.as_str(), contract_addr, &info.funds)
+    }
+
+    fn run_instantiate(
+        &self,
+        contract_addr: &str,
+        msg: &[u8],
+        info: &MessageInfo,
+    ) -> Result<SubMsgResponse, String> {
+        self.send_funds(contract_addr, info)?;
+        let response = self.with_engine(contract_addr, |engine, env| {
+            engine.run_instantiate(env, msg, info)
+        })?;
+        self.handle_response(contract_addr, response)
+    }
+
+    fn run_execute(
+        &self,
+        contract_addr: &str,
+        msg: &[u8],
+        info: &MessageInfo,
+    ) -> Result<SubMsgResponse, String> {
+        self.send_funds(contract_addr, info)?;
+        let response = self.with_engine(contract_addr, |engine, env| {
+            engine.run_execute(env, msg, info)
+        })?;
+        self.handle_response(contract_addr, response)
+    }
+
+    fn run_migrate(&self, contract_addr: &str, msg: &[u8]) -> Result<SubMsgResponse, String> {
+        let response =
+            self.with_engine(contract_addr, |engine, env| engine.run_migrate(env, msg))?;
+        self.handle_response(contract_addr, response)
+    }
+
+    fn handle_response(
+        &self,
+        contract_addr: &str,
+        response: Response<SpecialMsg>,
+    ) -> Result<SubMsgResponse, String> {
+        self.handle_messages(
+            contract_addr,
+            response.messages,
+            response.attributes,
+            response.events,
+            response.data,
+        )
+    }
+
+    // handle_messages turns the contract response into events, dispatching its sub messages
+    // and calling reply according to their ReplyOn
+    fn handle_messages(
+        &self,
+        contract_addr: &str,
+        messages: Vec<SubMsg<SpecialMsg>>,
+        attributes: Vec<Attribute>,
+        custom_events: Vec<Event>,
+        mut data: Option<Binary>,
+    ) -> Result<SubMsgResponse, String> {
+        let mut events = vec![Event::new("wasm")
+            .add_attribute("_contract_address", contract_addr)
+            .add_attributes(attributes)];
+        for event in custom_events {
+            events.push(
+                Event::new(format!("wasm-{}", event.ty))
+                    .add_attribute("_contract_address", contract_addr)
+                    .add_attributes(event.attributes),
+            );
+        }
+
+        for (index, msg) in messages.into_iter().enumerate() {
+            let result = match (
+                msg.reply_on,
+                self.dispatch_submessage(contract_addr, msg.msg),
+            ) {
+                (ReplyOn::Always | ReplyOn::Success, Ok(sub_response)) => {
+                    events.extend(sub_response.events.iter().cloned());
+                    SubMsgResult::Ok(sub_response)
+                }
+                (ReplyOn::Always | ReplyOn::Error, Err(err)) => SubMsgResult::Err(err),
+                (_, Ok(sub_response)) => {
+                    events.extend(sub_response.events);
+                    continue;
+                }
+                // no reply on error, the whole call fails
+                (_, Err(err)) => {
+                    return Err(format!("message {} of {}: {}", index, contract_addr, err))
+                }
+            };
+
+            let reply = Reply { id: msg.id, result };
+            let response =
+                self.with_engine(contract_addr, |engine, env| engine.run_reply(env, &reply))?;
+            let reply = self.handle_response(contract_addr, response)?;
+            events.extend(reply.events);
+            // data returned by reply overrides the response data
+            if reply.data.is_some() {
+                data = reply.data;
+            }
+        }
+
+        Ok(SubMsgResponse { events, data })
+    }
+
+    // dispatch_submessage runs a message of a contract response in its own transaction,
+    // so a failed sub message leaves no changes behind
+    fn dispatch_submessage(
+        &self,
+        sender_addr: &str,
+        msg: CosmosMsg<SpecialMsg>,
+    ) -> Result<SubMsgResponse, String> {
+        let description = describe_message(&msg);
+        self.transaction(|sim| sim.dispatch_message(sender_addr, msg))
+            .map_err(|err| format!("{} failed: {}", description, err))
+    }
+
+    // dispatch_message runs a message emitted by the sender contract
+    fn dispatch_message(
+        &self,
+        sender_addr: &str,
+        msg: CosmosMsg<SpecialMsg>,
+    ) -> Result<SubMsgResponse, String> {
+        match msg {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr,
+                msg,
+                funds,
+            }) => {
+                let response = self.run_execute(
+                    &contract_addr,
+                    msg.as_slice(),
+                    &MessageInfo {
+                        sender: Addr::unchecked(sender_addr),
+                        funds,
+                    },
+                )?;
+
+                let mut events =
+                    vec![Event::new("execute").add_attribute("_contract_address", &contract_addr)];
+                events.extend(response.events);
+                Ok(SubMsgResponse {
+                    events,
+                    data: encode_execute_response(response.data),
+                })
+            }
+            CosmosMsg::Wasm(WasmMsg::Instantiate {
+                admin,
+                code_id,
+                msg,
+                funds,
+                label,
+            }) => {
+                let info = MessageInfo {
+                    sender: Addr::unchecked(sender_addr),
+                    funds,
+                };
+                let (contract_addr, response) =
+                    self.instantiate_contract(&info, code_id, msg.as_slice(), &label, admin, None)?;
+                Ok(SubMsgResponse {
+                    events: response.events,
+                    data: Some(encode_instantiate_response(&contract_addr, response.data)),
+                })
+            }
+            CosmosMsg::Wasm(WasmMsg::Migrate {
+                contract_addr,
+                new_code_id,
+                msg,
+            }) => {
+                self.migrate_contract(&contract_addr, sender_addr, new_code_id)?;
+                let response = self.run_migrate(&contract_addr, msg.as_slice())?;
+
+                let mut events = vec![Event::new("migrate")
+                    .add_attribute("_contract_address", &contract_addr)
+                    .add_attribute("code_id", new_code_id.to_string())];
+                events.extend(response.events);
+                Ok(SubMsgResponse {
+                    events,
+                    data: encode_execute_response(response.data),
+                })
+            }
+            CosmosMsg::Wasm(WasmMsg::UpdateAdmin {
+                contract_addr,
+                admin,
+            }) => self.update_admin(sender_addr, &contract_addr, Some(admin)),
+            CosmosMsg::Wasm(WasmMsg::ClearAdmin { contract_addr }) => {
+                self.update_admin(sender_addr, &contract_addr, None)
+            }
+            CosmosMsg::Bank(BankMsg::Send { to_address, amount }) => {
+                self.chain
+                    .borrow_mut()
+                    .bank
+                    .send(sender_addr, to_address.as_str(), &amount)?;
+                Ok(SubMsgResponse {
+                    events: vec![Event::new("transfer")
+                        .add_attribute("recipient", to_address)
+                        .add_attribute("sender", sender_addr)
+                        .add_attribute("amount", coins_to_string(&amount))],
+                    data: None,
+                })
+            }
+            CosmosMsg::Bank(BankMsg::Burn { amount }) => {
+                self.chain.borrow_mut().bank.burn(sender_addr, &amount)?;
+                Ok(SubMsgResponse {
+                    events: vec![Event::new("burn")
+                        .add_attribute("burner", sender_addr)
+                        .add_attribute("amount", coins_to_string(&amount))],
+                    data: None,
+                })
+            }
+            CosmosMsg::Staking(StakingMsg::Delegate { validator, amount }) => {
+                let Chain { bank, staking, .. } = &mut *self.chain.borrow_mut();
+                staking.delegate(bank, sender_addr, &validator, &amount)?;
+                Ok(SubMsgResponse {
+                    events: vec![Event::new("delegate")
+                        .add_attribute("validator", validator)
+                        .add_attribute("amount", amount.to_string())],
+                    data: None,
+                })
+            }
+            CosmosMsg::Staking(StakingMsg::Undelegate { validator, amount }) => {
+                let Chain { bank, staking, .. } = &mut *self.chain.borrow_mut();
+                let completion_height =
+                    staking.undelegate(bank, sender_addr, &validator, &amount)?;
+                Ok(SubMsgResponse {
+                    events: vec![Event::new("unbond")
+                        .add_attribute("validator", validator)
+                        .add_attribute("amount", amount.to_string())
+                        .add_attribute("completion_height", completion_height.to_string())],
+                    data: None,
+                })
+            }
+            CosmosMsg::Staking(StakingMsg::Redelegate {
+                src_validator,
+                dst_validator,
+                amount,
+            }) => {
+                let Chain { bank, staking, .. } = &mut *self.chain.borrow_mut();
+                staking.redelegate(bank, sender_addr, &src_validator, &dst_validator, &amount)?;
+                Ok(SubMsgResponse {
+                    events: vec![Event::new("redelegate")
+                        .add_attribute("source_validator", src_validator)
+                        .add_attribute("destination_validator", dst_validator)
+                        .add_attribute("amount", amount.to_string())],
+                    data: None,
+                })
+            }
+            CosmosMsg::Distribution(DistributionMsg::SetWithdrawAddress { address }) => {
+                self.chain
+                    .borrow_mut()
+                    .staking
+                    .set_withdraw_address(sender_addr, &address);
+                Ok(SubMsgResponse {
+                    events: vec![Event::new("set_withdraw_address")
+                        .add_attribute("withdraw_address", address)],
+                    data: None,
+                })
+            }
+            CosmosMsg::Distribution(DistributionMsg::WithdrawDelegatorReward { validator }) => {
+                let Chain { bank, staking, .. } = &mut *self.chain.borrow_mut();
+                let rewards = staking.withdraw_rewards(bank, sender_addr, &validator)?;
+                Ok(SubMsgResponse {
+                    events: vec![Event::new("withdraw_rewards")
+                        .add_attribute("validator", validator)
+                        .add_attribute("amount", coins_to_string(&rewards))],
+                    data: None,
+                })
+            }
+            CosmosMsg::Ibc(IbcMsg::SendPacket {
+                channel_id,
+                data,
+                timeout,
+            }) => {
+                // packet waits in the relayer until the user relays or times it out
+                let packet = self.chain.borrow_mut().relayer.send_packet(
+                    sender_addr,
+                    &channel_id,
+                    data,
+                    timeout,
+                )?;
+                Ok(SubMsgResponse {
+                    events: vec![Event::new("send_packet")
+                        .add_attribute("packet_src_port", packet.src.port_id)
+                        .add_attribute("packet_src_channel", packet.src.channel_id)
+                        .add_attribute("packet_dst_port", packet.dest.port_id)
+                        .add_attribute("packet_dst_channel", packet.dest.channel_id)
+                        .add_attribute("packet_sequence", packet.sequence.to_string())],
+                    data: None,
+                })
+            }
+            CosmosMsg::Ibc(IbcMsg::CloseChannel { channel_id }) => {
+                self.close_channel(sender_addr, &channel_id)
+            }
+            CosmosMsg::Custom(msg) => {
+                let Chain {
+                    special_msgs, bank, ..
+                } = &mut *self.chain.borrow_mut();
+                custom_msg_execute(special_msgs, bank, sender_addr, &msg)
+            }
+            CosmosMsg::Stargate { type_url, value } => {
+                let (handler, msg) = self
+                    .chain
+                    .borrow()
+                    .stargate
+                    .decode_msg(&type_url, value.as_slice())?;
+                handler(self, sender_addr, &msg)
+            }
+            msg => Err(format!("Unsupported message: {:?}", msg)),
+        }
+    }
+
+    // instantiate_contract creates a new contract from a stored code, at the address derived
+    // from the salt when there is one, along with the message when fix_msg is set like wasmd.
+    // The response data is the one of the contract
+    fn instantiate_contract(
+        &self,
+        info: &MessageInfo,
+        code_id: u64,
+        msg: &[u8],
+        label: &str,
+        admin: Option<String>,
+        salt: Option<(&[u8], bool)>,
+    ) -> Result<(String, SubMsgResponse), String> {
+        let wasm_file = self.code_file(code_id)?;
+        let engine = self.compile(&wasm_file, &MockStorage::default())?;
+        let checksum = match salt {
+            Some(_) => self.checksum(code_id)?,
+            None => vec![],
+        };
+
+        let contract_addr = {
+            let chain = &mut *self.chain.borrow_mut();
+            let contract_addr = match salt {
+                Some((salt, fix_msg)) => {
+                    let creator = canonicalize(chain.bech32_prefix, info.sender.as_str())?;
+                    let msg = if fix_msg { msg } else { &[] };
+                    predictable_address(chain.bech32_prefix, &checksum, &creator, salt, msg)
+                }
+                None => chain.next_contract_address(code_id),
+            };
+            if chain.contracts.contains_key(&contract_addr) {
+                return Err(format!("Contract {} already exists", contract_addr));
+            }
+            // the code name and the instance sequence stand for the address
+            let code_name = Path::new(&wasm_file).file_stem().unwrap().to_str().unwrap();
+            let alias = format!("{}_{}", code_name, chain.contracts.len() + 1);
+            chain.aliases.insert(alias, contract_addr.to_owned());
+            let Chain {
+                engines, contracts, ..
+            } = chain;
+
+            contracts.insert(
+                contract_addr.to_owned(),
+                ContractMeta {
+                    code_id,
+                    creator: info.sender.to_string(),
+                    admin,
+                    label: label.to_string(),
+                    ibc_port: None,
+                },
+            );
+            engines.insert(contract_addr.to_owned(), Rc::new(RefCell::new(engine)));
+            contract_addr
+        };
+
+        let response = self.run_instantiate(&contract_addr, msg, info)?;
+
+        let mut events = vec![Event::new("instantiate")
+            .add_attribute("_contract_address", &contract_addr)
+            .add_attribute("code_id", code_id.to_string())];
+        events.extend(response.events);
+        Ok((
+            contract_addr,
+            SubMsgResponse {
+                events,
+                data: response.data,
+            },
+        ))
+    }
+
+    // migrate_contract replaces the code of the contract keeping its storage, only the admin can migrate
+    fn migrate_contract(
+        &self,
+        contract_addr: &str,
+        sender_addr: &str,
+        code_id: u64,
+    ) -> Result<(), String> {
+        match self.chain.borrow().contracts.get(contract_addr) {
+            None => return Err(format!("No such contract: {}", contract_addr)),
+            Some(meta) if meta.admin.as_deref() != Some(sender_addr) => {
+                return Err(format!(
+                    "Unauthorized: {} is not admin of {}",
+                    sender_addr, contract_addr
+                ))
+            }
+            Some(_) => {}
+        }
+
+        self.replace_code(contract_addr, code_id)?;
+
+        if let Some(meta) = self.chain.borrow_mut().contracts.get_mut(contract_addr) {
+            meta.code_id = code_id;
+        }
+        Ok(())
+    }
+
+    // update_admin changes the admin of a contract, only the current admin can do it
+    fn update_admin(
+        &self,
+        sender_addr: &str,
+        contract_addr: &str,
+        admin: Option<String>,
+    ) -> Result<SubMsgResponse, String> {
+        match self.chain.borrow_mut().contracts.get_mut(contract_addr) {
+            None => Err(format!("No such contract: {}", contract_addr)),
+            Some(meta) if meta.admin.as_deref() != Some(sender_addr) => Err(format!(
+                "Unauthorized: {} is not admin of {}",
+                sender_addr, contract_addr
+            )),
+            Some(meta) => {
+                let event = Event::new("update_contract_admin")
+                    .add_attribute("_contract_address", contract_addr)
+                    .add_attribute("new_admin_address", admin.clone().unwrap_or_default());
+                meta.admin = admin;
+                Ok(SubMsgResponse {
+                    events: vec![event],
+                    data: None,
+                })
+            }
+        }
+    }
+
+    fn begin_transaction(&self) {
+        let Chain {
+            engines,
+            aliases,
+            bank,
+            contracts,
+            instance_id,
+            transactions,
+            relayer,
+            staking,
+            ..
+        } = &mut *self.chain.borrow_mut();
+        transactions.push(Transaction {
+            aliases: aliases.clone(),
+            bank: bank.clone(),
+            contracts: contracts.clone(),
+            instance_id: *instance_id,
+            relayer: relayer.clone(),
+            staking: staking.clone(),
+        });
+        for engine in engines.values() {
+            engine
+                .borrow_mut()
+                .instance
+                .with_storage(|storage| {
+                    storage.checkpoint();
+                    Ok(())
+                })
+                .unwrap();
+        }
+    }
+
+    fn commit_transaction(&self) {
+        let Chain {
+            engines,
+            transactions,
+            ..
+        } = &mut *self.chain.borrow_mut();
+        transactions.pop();
+        for engine in engines.values() {
+            engine
+                .borrow_mut()
+                .instance
+                .with_storage(|storage| {
+                    storage.commit();
+                    Ok(())
+                })
+                .unwrap();
+        }
+    }
+
+    // rollback_transaction drops the contracts created after the transaction began,
+    // then restores storages, codes, admins, aliases, balances, delegations and queued packets
+    fn rollback_transaction(&self) {
+        let mut migrated = vec![];
+        {
+            let Chain {
+                engines,
+                aliases,
+                bank,
+                contracts,
+                instance_id,
+                transactions,
+                relayer,
+                staking,
+                ..
+            } = &mut *self.chain.borrow_mut();
+            let transaction = match transactions.pop() {
+                None => return,
+                Some(t) => t,
+            };
+
+            engines.retain(|contract_addr, _| transaction.contracts.contains_key(contract_addr));
+            for (contract_addr, engine) in engines.iter() {
+                engine
+                    .borrow_mut()
+                    .instance
+                    .with_storage(|storage| {
+                        storage.rollback();
+                        Ok(())
+                    })
+                    .unwrap();
+
+                // migrated contract gets its previous code back
+                let code_id = transaction.contracts[contract_addr].code_id;
+                if contracts.get(contract_addr).map(|meta| meta.code_id) != Some(code_id) {
+                    migrated.push((contract_addr.to_owned(), code_id));
+                }
+            }
+
+            *aliases = transaction.aliases;
+            *bank = transaction.bank;
+            *contracts = transaction.contracts;
+            *instance_id = transaction.instance_id;
+            *relayer = transaction.relayer;
+            *staking = transaction.staking;
+        }
+
+        for (contract_addr, code_id) in migrated {
+            if let Err(err) = self.replace_code(&contract_addr, code_id) {
+                println!("{}", err.red());
+            }
+        }
+    }
+
+    // open_channel runs the channel handshake, OpenInit and OpenAck on contract a, OpenTry and OpenConfirm on contract b
+    pub fn open_channel(
+        &self,
+        contract_a: &str,
+        contract_b: &str,
+        order: IbcOrder,
+        version: &str,
+    ) -> Result<SubMsgResponse, String> {
+        let index = {
+            let Chain {
+                engines,
+                contracts,
+                relayer,
+                ..
+            } = &mut *self.chain.borrow_mut();
+            if engines.get(contract_a).is_none() || engines.get(contract_b).is_none() {
+                return Err(format!("No such contracts: {}, {}", contract_a, contract_b));
+            }
+            // both contracts are bound to their port from now on
+            for contract_addr in [contract_a, contract_b] {
+                if let Some(meta) = contracts.get_mut(contract_addr) {
+                    meta.ibc_port = Some(Relayer::port_id(contract_addr));
+                }
+            }
+            relayer.new_channel(contract_a, contract_b, order, version)
+        };
+        let channel = |sim: &Simulator| sim.chain.borrow().relayer.channels[index].clone();
+
+        // contracts may propose another version during the handshake
+        let msg = IbcChannelOpenMsg::new_init(channel(self).ibc_channel(0));
+        if let Some(version) = self.with_engine(contract_a, |engine, env| {
+            engine.run_ibc_channel_open(env, &msg)
+        })? {
+            self.chain.borrow_mut().relayer.channels[index].version = version;
+        }
+        let counterparty_version = channel(self).version;
+        let msg = IbcChannelOpenMsg::new_try(channel(self).ibc_channel(1), counterparty_version);
+        if let Some(version) = self.with_engine(contract_b, |engine, env| {
+            engine.run_ibc_channel_open(env, &msg)
+        })? {
+            self.chain.borrow_mut().relayer.channels[index].version = version;
+        }
+
+        // contracts can send packets as soon as they are connected
+        self.chain.borrow_mut().relayer.channels[index].open = true;
+        let channel = channel(self);
+        let mut events = vec![Event::new("channel_open")
+            .add_attribute("port_id", &channel.ends[0].endpoint.port_id)
+            .add_attribute("channel_id", &channel.ends[0].endpoint.channel_id)
+            .add_attribute("counterparty_port_id", &channel.ends[1].endpoint.port_id)
+            .add_attribute(
+                "counterparty_channel_id",
+                &channel.ends[1].endpoint.channel_id,
+            )
+            .add_attribute("version", &channel.version)];
+
+        let msg = IbcChannelConnectMsg::new_ack(channel.ibc_channel(0), channel.version.to_owned());
+        let response = self.with_engine(contract_a, |engine, env| {
+            engine.run_ibc_channel_connect(env, &msg)
+        })?;
+        let response = self.handle_messages(
+            contract_a,
+            response.messages,
+            response.attributes,
+            response.events,
+            None,
+        )?;
+        events.extend(response.events);
+        let msg = IbcChannelConnectMsg::new_confirm(channel.ibc_channel(1));
+        let response = self.with_engine(contract_b, |engine, env| {
+            engine.run_ibc_channel_connect(env, &msg)
+        })?;
+        let response = self.handle_messages(
+            contract_b,
+            response.messages,
+            response.attributes,
+            response.events,
+            None,
+        )?;
+        events.extend(response.events);
+
+        Ok(SubMsgResponse { events, data: None })
+    }
+
+    // close_channel runs CloseInit on the contract closing the channel and CloseConfirm on its counterparty
+    pub fn close_channel(
+        &self,
+        contract_addr: &str,
+        channel_id: &str,
+    ) -> Result<SubMsgResponse, String> {
+        let (channel, side) = {
+            let Chain { relayer, .. } = &mut *self.chain.borrow_mut();
+            let (index, side) = match relayer.find_end(contract_addr, channel_id) {
+                None => {
+                    return Err(format!(
+                        "channel {} not found for {}",
+                        channel_id, contract_addr
+                    ))
+                }
+                Some(found) => found,
+            };
+            if !relayer.channels[index].open {
+                return Err(format!("channel {} is not open", channel_id));
+            }
+            relayer.channels[index].open = false;
+            (relayer.channels[index].clone(), side)
+        };
+
+        let mut events = vec![Event::new("channel_close")
+            .add_attribute("port_id", &channel.ends[side].endpoint.port_id)
+            .add_attribute("channel_id", channel_id)];
+        let msg = IbcChannelCloseMsg::new_init(channel.ibc_channel(side));
+        let response = self.with_engine(contract_addr, |engine, env| {
+            engine.run_ibc_channel_close(env, &msg)
+        })?;
+        let response = self.handle_messages(
+            contract_addr,
+            response.messages,
+            response.attributes,
+            response.events,
+            None,
+        )?;
+        events.extend(response.events);
+
+        let counterparty = &channel.ends[1 - side].contract_addr;
+        let msg = IbcChannelCloseMsg::new_confirm(channel.ibc_channel(1 - side));
+        let response = self.with_engine(counterparty, |engine, env| {
+            engine.run_ibc_channel_close(env, &msg)
+        })?;
+        let response = self.handle_messages(
+            counterparty,
+            response.messages,
+            response.attributes,
+            response.events,
+            None,
+        )?;
+        events.extend(response.events);
+
+        Ok(SubMsgResponse { events, data: None })
+    }
+
+    // relay_packet delivers the oldest pending packet and queues the acknowledgement written by the receiver
+    pub fn relay_packet(&self) -> Result<SubMsgResponse, String> {
+        let (packet, contract_addr) = {
+            let Chain { relayer, block, .. } = &*self.chain.borrow();
+            let packet = match relayer.packets.front() {
+                None => return Err("No pending packet".to_string()),
+                Some(packet) => packet.clone(),
+            };
+            let contract_addr = match relayer.contract_of(&packet.dest) {
+                None => return Err(format!("No contract bound to {}", packet.dest.port_id)),
+                Some(contract_addr) => contract_addr,
+            };
+            if is_timed_out(&packet, block) {
+                return Err(format!(
+                    "packet {} on {} timed out",
+                    packet.sequence, packet.src.channel_id
+                ));
+            }
+            (packet, contract_addr)
+        };
+
+        let msg = IbcPacketReceiveMsg::new(
+            packet.clone(),
+            Addr::unchecked(relayer_addr(self.chain.borrow().bech32_prefix)),
+        );
+        let response = self.with_engine(&contract_addr, |engine, env| {
+            engine.run_ibc_packet_receive(env, &msg)
+        })?;
+        let ack = response.acknowledgement;
+        let response = self.handle_messages(
+            &contract_addr,
+            response.messages,
+            response.attributes,
+            response.events,
+            None,
+        )?;
+
+        let mut events = vec![Event::new("recv_packet")
+            .add_attribute("packet_dst_channel", &packet.dest.channel_id)
+            .add_attribute("packet_sequence", packet.sequence.to_string())];
+        events.extend(response.events);
+        events.push(
+            Event::new("write_acknowledgement")
+                .add_attribute("packet_sequence", packet.sequence.to_string())
+                .add_attribute("packet_ack", ack.to_base64()),
+        );
+        let Chain { relayer, .. } = &mut *self.chain.borrow_mut();
+        relayer.packets.pop_front();
+        relayer.acks.push_back((packet, ack));
+
+        Ok(SubMsgResponse { events, data: None })
+    }
+
+    // ack_packet relays the oldest acknowledgement back to the contract that sent the packet
+    pub fn ack_packet(&self) -> Result<SubMsgResponse, String> {
+        let (packet, ack, contract_addr) = {
+            let Chain { relayer, .. } = &*self.chain.borrow();
+            let (packet, ack) = match relayer.acks.front() {
+                None => return Err("No pending acknowledgement".to_string()),
+                Some(ack) => ack.clone(),
+            };
+            let contract_addr = match relayer.contract_of(&packet.src) {
+                None => return Err(format!("No contract bound to {}", packet.src.port_id)),
+                Some(contract_addr) => contract_addr,
+            };
+            (packet, ack, contract_addr)
+        };
+
+        let sequence = packet.sequence;
+        let msg = IbcPacketAckMsg::new(
+            IbcAcknowledgement::new(ack),
+            packet,
+            Addr::unchecked(relayer_addr(self.chain.borrow().bech32_prefix)),
+        );
+        let response = self.with_engine(&contract_addr, |engine, env| {
+            engine.run_ibc_packet_ack(env, &msg)
+        })?;
+        let response = self.handle_messages(
+            &contract_addr,
+            response.messages,
+            response.attributes,
+            response.events,
+            None,
+        )?;
+        self.chain.borrow_mut().relayer.acks.pop_front();
+
+        let mut events = vec![Event::new("acknowledge_packet")
+            .add_attribute("packet_src_channel", &msg.original_packet.src.channel_id)
+            .add_attribute("packet_sequence", sequence.to_string())];
+        events.extend(response.events);
+        Ok(SubMsgResponse { events, data: None })
+    }
+
+    // timeout_packet drops the oldest pending packet once its timeout is reached
+    // and tells the contract that sent it
+    pub fn timeout_packet(&self) -> Result<SubMsgResponse, String> {
+        let (packet, contract_addr) = {
+            let Chain { relayer, block, .. } = &*self.chain.borrow();
+            let packet = match relayer.packets.front() {
+                None => return Err("No pending packet".to_string()),
+                Some(packet) => packet.clone(),
+            };
+            let contract_addr = match relayer.contract_of(&packet.src) {
+                None => return Err(format!("No contract bound to {}", packet.src.port_id)),
+                Some(contract_addr) => contract_addr,
+            };
+            if !is_timed_out(&packet, block) {
+                return Err("Packet has not timed out".to_string());
+            }
+            (packet, contract_addr)
+        };
+
+        let mut events = vec![Event::new("timeout_packet")
+            .add_attribute("packet_src_channel", &packet.src.channel_id)
+            .add_attribute("packet_sequence", packet.sequence.to_string())];
+        let msg = IbcPacketTimeoutMsg::new(
+            packet,
+            Addr::unchecked(relayer_addr(self.chain.borrow().bech32_prefix)),
+        );
+        let response = self.with_engine(&contract_addr, |engine, env| {
+            engine.run_ibc_packet_timeout(env, &msg)
+        })?;
+        let response = self.handle_messages(
+            &contract_addr,
+            response.messages,
+            response.attributes,
+            response.events,
+            None,
+        )?;
+        self.chain.borrow_mut().relayer.packets.pop_front();
+
+        events.extend(response.events);
+        Ok(SubMsgResponse { events, data: None })
+    }
+}
+
+pub fn coins_to_string(coins: &[Coin]) -> String {
+    coins
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+// parse_coins reads coins written like coins_to_string does, 100orai,5usdt
+pub fn parse_coins(coins: &str) -> Result<Vec<Coin>, String> {
+    coins
+        .split(',')
+        .map(|coin| coin.trim())
+        .filter(|coin| !coin.is_empty())
+        .map(|coin| {
+            let index = coin
+                .find(|c: char| !c.is_ascii_digit())
+                .ok_or_else(|| format!("Missing denom in {}", coin))?;
+            let (amount, denom) = coin.split_at(index);
+            if !is_denom(denom) {
+                return Err(format!("Wrong denom in {}", coin));
+            }
+            match amount.parse::<u128>() {
+                Ok(amount) => Ok(Coin::new(amount, denom)),
+                Err(_) => Err(format!("Wrong amount in {}", coin)),
+            }
+        })
+        .collect()
+}
+
+// is_denom checks a denom like the sdk does, [a-zA-Z][a-zA-Z0-9/:._-]{2,127}
+fn is_denom(denom: &str) -> bool {
+    (3..=128).contains(&denom.len())
+        && denom.starts_with(|c: char| c.is_ascii_alphabetic())
+        && denom
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/:._-".contains(c))
+}
+
+// describe_message tells which message failed in error reports
+fn describe_message(msg: &CosmosMsg<SpecialMsg>) -> String {
+    match msg {
+        CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }) => {
+            format!("execute {}", contract_addr)
+        }
+        CosmosMsg::Wasm(WasmMsg::Instantiate { code_id, label, .. }) => {
+            format!("instantiate code {} ({})", code_id, label)
+        }
+        CosmosMsg::Wasm(WasmMsg::Migrate {
+            contract_addr,
+            new_code_id,
+            ..
+        }) => format!("migrate {} to code {}", contract_addr, new_code_id),
+        CosmosMsg::Wasm(WasmMsg::UpdateAdmin { contract_addr, .. }) => {
+            format!("update admin of {}", contract_addr)
+        }
+        CosmosMsg::Wasm(WasmMsg::ClearAdmin { contract_addr }) => {
+            format!("clear admin of {}", contract_addr)
+        }
+        CosmosMsg::Bank(BankMsg::Send { to_address, amount }) => {
+            format!("send {} to {}", coins_to_string(amount), to_address)
+        }
+        CosmosMsg::Bank(BankMsg::Burn { amount }) => format!("burn {}", coins_to_string(amount)),
+        CosmosMsg::Staking(StakingMsg::Delegate { validator, amount }) => {
+            format!("delegate {} to {}", amount, validator)
+        }
+        CosmosMsg::Staking(StakingMsg::Undelegate { validator, amount }) => {
+            format!("undelegate {} from {}", amount, validator)
+        }
+        CosmosMsg::Staking(StakingMsg::Redelegate {
+            src_validator,
+            dst_validator,
+            amount,
+        }) => format!(
+            "redelegate {} from {} to {}",
+            amount, src_validator, dst_validator
+        ),
+        CosmosMsg::Distribution(DistributionMsg::WithdrawDelegatorReward { validator }) => {
+            format!("withdraw rewards from {}", validator)
+        }
+        CosmosMsg::Ibc(IbcMsg::SendPacket { channel_id, .. }) => {
+            format!("send packet on {}", channel_id)
+        }
+        CosmosMsg::Ibc(IbcMsg::CloseChannel { channel_id }) => {
+            format!("close channel {}", channel_id)
+        }
+        CosmosMsg::Custom(msg) => format!("custom {}", msg.0),
+        CosmosMsg::Stargate { type_url, .. } => format!("stargate {}", type_url),
+        _ => format!("{:?}", msg),
+    }
+}
+
+// parse_duration reads a duration like 30s, 5m, 2h or 1d as seconds, a bare number is in seconds
+pub fn parse_duration(duration: &str) -> Result<u64, String> {
+    let (value, unit) = match duration.find(|c: char| !c.is_ascii_digit()) {
+        None => (duration, "s"),
+        Some(index) => duration.split_at(index),
+    };
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3_600,
+        "d" => 86_400,
+        "w" => 604_800,
+        _ => return Err(format!("Wrong duration unit {}", unit)),
+    };
+    value
+        .parse::<u64>()
+        .ok()
+        .and_then(|value| value.checked_mul(seconds))
+        .ok_or_else(|| format!("Wrong duration {}", duration))
+}
+
+// encode_bytes_field appends a length delimited protobuf field
+fn encode_bytes_field(field: u8, value: &[u8], buf: &mut Vec<u8>) {
+    if value.is_empty() {
+        return;
+    }
+    buf.push(field << 3 | 2);
+    let mut len = value.len();
+    while len >= 0x80 {
+        buf.push((len as u8 & 0x7f) | 0x80);
+        len >>= 7;
+    }
+    buf.push(len as u8);
+    buf.extend_from_slice(value);
+}
+
+// sub message data is wrapped like wasmd MsgInstantiateContractResponse
+fn encode_instantiate_response(contract_addr: &str, data: Option<Binary>) -> Binary {
+    let mut buf = vec![];
+    encode_bytes_field(1, contract_addr.as_bytes(), &mut buf);
+    encode_bytes_field(2, data.unwrap_or_default().as_slice(), &mut buf);
+    Binary::from(buf)
+}
+
+// sub message data is wrapped like wasmd MsgExecuteContractResponse and MsgMigrateContractResponse
+fn encode_execute_response(data: Option<Binary>) -> Option<Binary> {
+    data.map(|data| {
+        let mut buf = vec![];
+        encode_bytes_field(1, data.as_slice(), &mut buf);
+        Binary::from(buf)
+    })
+}
+
+// new_stargate registers the stargate paths served by the simulated modules,
+// their protobuf types come from the descriptor sets given with --proto
+fn new_stargate() -> Stargate {
+    let mut stargate = Stargate::default();
+    stargate.register_query("/cosmos.bank.v1beta1.Query/Balance", |chain, request| {
+        let address: String = proto_field(request, "address")?;
+        let denom: String = proto_field(request, "denom")?;
+        let amount = chain.bank.balance(&address, &denom);
+        Ok(json!({ "balance": Coin { denom, amount } }))
+    });
+    stargate.register_query(
+        "/cosmos.bank.v1beta1.Query/AllBalances",
+        |chain, request| {
+            let address: String = proto_field(request, "address")?;
+            Ok(json!({ "balances": chain.bank.all_balances(&address) }))
+        },
+    );
+    stargate.register_msg(
+        "/cosmwasm.wasm.v1.MsgInstantiateContract2",
+        |sim, sender_addr, msg| {
+            check_signer(sender_addr, msg, "sender")?;
+            let info = MessageInfo {
+                sender: Addr::unchecked(sender_addr),
+                funds: proto_field(msg, "funds")?,
+            };
+            let code_id: Uint64 = proto_field(msg, "code_id")?;
+            let init_msg: Binary = proto_field(msg, "msg")?;
+            let label: String = proto_field(msg, "label")?;
+            let admin: String = proto_field(msg, "admin")?;
+            let salt: Binary = proto_field(msg, "salt")?;
+            let fix_msg: bool = proto_field(msg, "fix_msg")?;
+            let (contract_addr, response) = sim.instantiate_contract(
+                &info,
+                code_id.u64(),
+                init_msg.as_slice(),
+                &label,
+                Some(admin).filter(|admin| !admin.is_empty()),
+                Some((salt.as_slice(), fix_msg)),
+            )?;
+            Ok(SubMsgResponse {
+                events: response.events,
+                data: Some(encode_instantiate_response(&contract_addr, response.data)),
+            })
+        },
+    );
+    stargate.register_msg("/cosmos.bank.v1beta1.MsgSend", |sim, sender_addr, msg| {
+        check_signer(sender_addr, msg, "from_address")?;
+        sim.dispatch_message(
+            sender_addr,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: proto_field(msg, "to_address")?,
+                amount: proto_field(msg, "amount")?,
+            }),
+        )
+    });
+    stargate.register_msg(
+        "/cosmos.staking.v1beta1.MsgDelegate",
+        |sim, sender_addr, msg| {
+            check_signer(sender_addr, msg, "delegator_address")?;
+            sim.dispatch_message(
+                sender_addr,
+                CosmosMsg::Staking(StakingMsg::Delegate {
+                    validator: proto_field(msg, "validator_address")?,
+                    amount: proto_field(msg, "amount")?,
+                }),
+            )
+        },
+    );
+    stargate.register_msg(
+        "/cosmos.staking.v1beta1.MsgUndelegate",
+        |sim, sender_addr, msg| {
+            check_signer(sender_addr, msg, "delegator_address")?;
+            sim.dispatch_message(
+                sender_addr,
+                CosmosMsg::Staking(StakingMsg::Undelegate {
+                    validator: proto_field(msg, "validator_address")?,
+                    amount: proto_field(msg, "amount")?,
+                }),
+            )
+        },
+    );
+    stargate.register_msg(
+        "/cosmos.distribution.v1beta1.MsgWithdrawDelegatorReward",
+        |sim, sender_addr, msg| {
+            check_signer(sender_addr, msg, "delegator_address")?;
+            sim.dispatch_message(
+                sender_addr,
+                CosmosMsg::Distribution(DistributionMsg::WithdrawDelegatorReward {
+                    validator: proto_field(msg, "validator_address")?,
+                }),
+            )
+        },
+    );
+    stargate
+}
+
+// proto_field reads a field of a decoded protobuf message
+fn proto_field<T: DeserializeOwned>(msg: &Value, field: &str) -> Result<T, String> {
+    serde_json::from_value(msg[field].clone()).map_err(|e| format!("{}: {}", field, e))
+}
+
+// check_signer rejects messages signed for another account than the contract
+fn check_signer(sender_addr: &str, msg: &Value, field: &str) -> Result<(), String> {
+    let signer: String = proto_field(msg, field)?;
+    if signer.ne(sender_addr) {
+        return Err(format!(
+            "{} {} is not the sender {}",
+            field, signer, sender_addr
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_advance_blocks() {
+    use cosmwasm_std::{Decimal, Validator};
+
+    let simulator = Simulator::new();
+    simulator.set_balance("alice", &[Coin::new(1000, DENOM)]);
+    {
+        let Chain {
+            bank,
+            staking,
+            block,
+            ..
+        } = &mut *simulator.chain_mut();
+        let validator = Validator {
+            address: "val".to_string(),
+            commission: Decimal::zero(),
+            max_commission: Decimal::one(),
+            max_change_rate: Decimal::one(),
+        };
+        *staking = StakingQuerier::new(DENOM, block.height, &[validator], &[]);
+        staking
+            .delegate(bank, "alice", "val", &Coin::new(1000, DENOM))
+            .unwrap();
+        staking
+            .undelegate(bank, "alice", "val", &Coin::new(1000, DENOM))
+            .unwrap();
+    }
+    assert_eq!(simulator.balance("alice", DENOM), Uint128::zero());
+
+    // coins are back once the unbonding period is over
+    simulator.advance_blocks(99).unwrap();
+    assert_eq!(simulator.balance("alice", DENOM), Uint128::zero());
+    simulator.advance_blocks(1).unwrap();
+    assert_eq!(simulator.balance("alice", DENOM), Uint128::from(1000u64));
+    assert_eq!(simulator.chain().block.height, BLOCK_HEIGHT + 100);
+}
+
+#[test]
+fn test_set_height() {
+    use cosmwasm_std::{Decimal, Validator};
+
+    let simulator = Simulator::new();
+    simulator.set_balance("alice", &[Coin::new(1000, DENOM)]);
+    {
+        let Chain {
+            bank,
+            staking,
+            block,
+            ..
+        } = &mut *simulator.chain_mut();
+        let validator = Validator {
+            address: "val".to_string(),
+            commission: Decimal::zero(),
+            max_commission: Decimal::one(),
+            max_change_rate: Decimal::one(),
+        };
+        *staking = StakingQuerier::new(DENOM, block.height, &[validator], &[]);
+        staking
+            .delegate(bank, "alice", "val", &Coin::new(1000, DENOM))
+            .unwrap();
+        staking
+            .undelegate(bank, "alice", "val", &Coin::new(1000, DENOM))
+            .unwrap();
+    }
+
+    // jumping past the unbonding period pays it back at once, the time follows the height
+    simulator.set_height(BLOCK_HEIGHT + 200).unwrap();
+    assert_eq!(simulator.balance("alice", DENOM), Uint128::from(1000u64));
+    assert_eq!(
+        simulator.chain().block.time,
+        Timestamp::from_seconds(BLOCK_TIME + 200 * BLOCK_INTERVAL)
+    );
+
+    // going back only moves the height
+    simulator.set_height(BLOCK_HEIGHT).unwrap();
+    assert_eq!(simulator.chain().block.height, BLOCK_HEIGHT);
+    assert_eq!(
+        simulator.chain().block.time,
+        Timestamp::from_seconds(BLOCK_TIME + 200 * BLOCK_INTERVAL)
+    );
+
+    // a height the time can not follow is refused
+    assert!(simulator.set_height(u64::MAX).is_err());
+    assert_eq!(simulator.chain().block.height, BLOCK_HEIGHT);
+}
+
+#[test]
+fn test_advance_time() {
+    let simulator = Simulator::new();
+    assert_eq!(parse_duration("1d"), Ok(86_400));
+    assert_eq!(parse_duration("90"), Ok(90));
+    assert!(parse_duration("1y").is_err());
+    assert!(parse_duration("18446744073709551615d").is_err());
+
+    // blocks are produced every 5 seconds, the remainder only moves the time
+    simulator
+        .advance_time(parse_duration("1d").unwrap() + 3)
+        .unwrap();
+    let block = simulator.chain().block.clone();
+    assert_eq!(block.height, BLOCK_HEIGHT + 17_280);
+    assert_eq!(block.time, Timestamp::from_seconds(BLOCK_TIME + 86_403));
+
+    // a year is a single jump
+    simulator.chain_mut().tx_index = 2;
+    simulator
+        .advance_time(parse_duration("365d").unwrap())
+        .unwrap();
+    let chain = simulator.chain();
+    assert_eq!(chain.block.height, block.height + 6_307_200);
+    assert_eq!(chain.tx_index, 0);
+}
+
+#[test]
+fn test_block_commit() {
+    let simulator = Simulator::new();
+    simulator.chain_mut().auto_commit = false;
+
+    // transactions pile up in the block until it is committed, failed ones are dropped
+    assert_eq!(simulator.deliver_tx(|_| Ok(())), Ok(()));
+    assert!(simulator
+        .deliver_tx(|_| Err::<(), _>("fail".to_string()))
+        .is_err());
+    assert_eq!(simulator.deliver_tx(|_| Ok(())), Ok(()));
+    assert_eq!(simulator.chain().tx_index, 2);
+    assert_eq!(simulator.chain().block.height, BLOCK_HEIGHT);
+
+    simulator.end_block();
+    assert_eq!(simulator.chain().tx_index, 0);
+    assert_eq!(simulator.chain().block.height, BLOCK_HEIGHT + 1);
+
+    // in auto commit every transaction gets its own block
+    simulator.chain_mut().auto_commit = true;
+    simulator.deliver_tx(|_| Ok(())).unwrap();
+    assert_eq!(simulator.chain().tx_index, 0);
+    assert_eq!(simulator.chain().block.height, BLOCK_HEIGHT + 2);
+}
+
+#[test]
+fn test_parse_coins() {
+    assert_eq!(
+        parse_coins("100orai, 5ibc/usdt"),
+        Ok(vec![Coin::new(100, DENOM), Coin::new(5, "ibc/usdt")])
+    );
+    assert_eq!(parse_coins(""), Ok(vec![]));
+    assert!(parse_coins("100").is_err());
+    assert!(parse_coins("orai").is_err());
+    // denoms follow the sdk rule
+    assert!(parse_coins("100or").is_err());
+    assert!(parse_coins("100orai 5usdt").is_err());
+    assert!(parse_coins("100orai$").is_err());
+}
+
+#[test]
+fn test_accounts() {
+    let simulator = Simulator::new();
+    simulator.add_account("bob", &[Coin::new(10, "usdt")]);
+    simulator.add_account("alice", &[Coin::new(100, DENOM), Coin::new(5, "usdt")]);
+    simulator.add_account("bob", &[Coin::new(10, "usdt")]);
+    assert_eq!(simulator.chain().accounts, vec!["alice", "bob"]);
+    assert_eq!(simulator.balance("bob", "usdt"), Uint128::from(20u64));
+
+    simulator.mint("carol", &[Coin::new(1, DENOM)]);
+    simulator.burn("alice", &[Coin::new(5, "usdt")]).unwrap();
+    assert!(simulator.burn("alice", &[Coin::new(5, "usdt")]).is_err());
+    assert_eq!(
+        simulator.chain().bank.addresses(),
+        vec!["alice", "bob", "carol"]
+    );
+    assert_eq!(
+        simulator.chain().bank.all_balances("alice"),
+        vec![Coin::new(100, DENOM)]
+    );
+}
+
+#[test]
+fn test_aliases() {
+    let simulator = Simulator::new();
+    let alice = simulator.add_alias("alice");
+    assert!(alice.starts_with("orai1"));
+    assert_eq!(simulator.add_alias("alice"), alice);
+    assert_eq!(simulator.add_alias(&alice), alice);
+
+    let chain = simulator.chain();
+    assert_eq!(chain.resolve("alice"), alice);
+    assert_eq!(chain.resolve("bob"), "bob");
+    assert_eq!(chain.alias(&alice), Some("alice"));
+}
+
+#[test]
+fn test_contract_sequence() {
+    let simulator = Simulator::new();
+    let first = simulator.chain_mut().next_contract_address(1);
+    assert_eq!(first, contract_address(BECH32_PREFIX, 1, 1));
+
+    // a rolled back instantiation gives its sequence and its alias back
+    let result = simulator.transaction(|sim| {
+        let mut chain = sim.chain_mut();
+        let addr = chain.next_contract_address(2);
+        chain.aliases.insert("code_1".to_string(), addr);
+        Err::<(), _>("fail".to_string())
+    });
+    assert!(result.is_err());
+    assert_eq!(simulator.chain().resolve("code_1"), "code_1");
+    let second = simulator.chain_mut().next_contract_address(2);
+    assert_eq!(second, contract_address(BECH32_PREFIX, 2, 2));
+}
+
+#[test]
+fn test_timeout_packet() {
+    use cosmwasm_std::{IbcTimeout, IbcTimeoutBlock};
+
+    let simulator = Simulator::new();
+    {
+        let relayer = &mut simulator.chain_mut().relayer;
+        let index = relayer.new_channel("ping", "pong", IbcOrder::Unordered, "ping-1");
+        relayer.channels[index].open = true;
+        let timeout = IbcTimeout::with_block(IbcTimeoutBlock {
+            revision: 0,
+            height: BLOCK_HEIGHT + 10,
+        });
+        relayer
+            .send_packet("ping", "channel-0", Binary::from(b"hi"), timeout)
+            .unwrap();
+    }
+
+    // the packet stays pending until its timeout height
+    assert_eq!(
+        simulator.timeout_packet().unwrap_err(),
+        "Packet has not timed out"
+    );
+    assert_eq!(simulator.chain().relayer.packets.len(), 1);
+}
+
+#[test]
+fn test_engine_already_running() {
+    // a contract querying itself borrows its running engine again
+    let engine = RefCell::new(0);
+    let running = borrow_engine(&engine, "orai1contract").unwrap();
+    assert_eq!(
+        borrow_engine(&engine, "orai1contract").unwrap_err(),
+        "Contract orai1contract is already running"
+    );
+    drop(running);
+    assert!(borrow_engine(&engine, "orai1contract").is_ok());
+}
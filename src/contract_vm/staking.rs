@@ -0,0 +1,437 @@
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    to_binary, Addr, AllDelegationsResponse, AllValidatorsResponse, Binary, BondedDenomResponse,
+    Coin, ContractResult, Decimal, FullDelegation, QuerierResult, StakingQuery, SystemError,
+    SystemResult, Uint128, Validator, ValidatorResponse,
+};
+
+use crate::contract_vm::bank::Bank;
+
+// blocks an undelegation waits before the coins are back in the bank
+const DEFAULT_UNBONDING_BLOCKS: u64 = 100;
+
+/// DelegationResponse is data format returned from StakingRequest::Delegation query
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct DelegationResponse {
+    pub delegation: Option<FullDelegation>,
+}
+
+/// DelegationFixture is a delegation as written in the staking fixture file,
+/// can_redelegate defaults to the whole amount
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DelegationFixture {
+    pub delegator: Addr,
+    pub validator: String,
+    pub amount: Coin,
+    #[serde(default)]
+    pub can_redelegate: Option<Coin>,
+    #[serde(default)]
+    pub accumulated_rewards: Vec<Coin>,
+}
+
+/// StakingFixture declares the validators and delegations of the simulated chain,
+/// reward_rate is the share of a delegation paid as reward every block
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct StakingFixture {
+    #[serde(default)]
+    pub denom: Option<String>,
+    #[serde(default)]
+    pub validators: Vec<Validator>,
+    #[serde(default)]
+    pub delegations: Vec<DelegationFixture>,
+    #[serde(default)]
+    pub reward_rate: Option<Decimal>,
+    #[serde(default)]
+    pub unbonding_blocks: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+struct Delegation {
+    // accumulated_rewards only holds the rewards settled before start_height
+    delegation: FullDelegation,
+    start_height: u64,
+}
+
+#[derive(Clone, Debug)]
+struct Unbonding {
+    delegator: String,
+    amount: Coin,
+    completion_height: u64,
+}
+
+/// StakingQuerier keeps validators and delegations of the simulated chain, rewards accrue
+/// every block and undelegated coins come back once the unbonding period is over
+#[derive(Clone, Default)]
+pub struct StakingQuerier {
+    denom: String,
+    validators: Vec<Validator>,
+    delegations: Vec<Delegation>,
+    unbondings: Vec<Unbonding>,
+    withdraw_addresses: Vec<(String, String)>,
+    reward_rate: Decimal,
+    unbonding_blocks: u64,
+    // current block height, moved by the simulator
+    height: u64,
+}
+
+impl StakingQuerier {
+    pub fn new(
+        denom: &str,
+        height: u64,
+        validators: &[Validator],
+        delegations: &[FullDelegation],
+    ) -> Self {
+        StakingQuerier {
+            denom: denom.to_string(),
+            validators: validators.to_vec(),
+            delegations: delegations
+                .iter()
+                .map(|d| Delegation {
+                    delegation: d.clone(),
+                    start_height: height,
+                })
+                .collect(),
+            unbondings: vec![],
+            withdraw_addresses: vec![],
+            reward_rate: Decimal::zero(),
+            unbonding_blocks: DEFAULT_UNBONDING_BLOCKS,
+            height,
+        }
+    }
+
+    // from_fixture uses the given bonded denom unless the fixture sets one
+    pub fn from_fixture(denom: &str, height: u64, fixture: StakingFixture) -> Self {
+        let delegations: Vec<FullDelegation> = fixture
+            .delegations
+            .into_iter()
+            .map(|d| FullDelegation {
+                can_redelegate: d.can_redelegate.unwrap_or(d.amount.clone()),
+                delegator: d.delegator,
+                validator: d.validator,
+                amount: d.amount,
+                accumulated_rewards: d.accumulated_rewards,
+            })
+            .collect();
+        let mut staking = StakingQuerier::new(
+            fixture.denom.as_deref().unwrap_or(denom),
+            height,
+            &fixture.validators,
+            &delegations,
+        );
+        staking.reward_rate = fixture.reward_rate.unwrap_or_default();
+        staking.unbonding_blocks = fixture.unbonding_blocks.unwrap_or(DEFAULT_UNBONDING_BLOCKS);
+        staking
+    }
+
+    pub fn set_height(&mut self, height: u64) {
+        self.height = height;
+    }
+
+    // rewards of a delegation, the settled ones plus those accrued since start_height
+    fn rewards(&self, delegation: &Delegation) -> Vec<Coin> {
+        let mut rewards = delegation.delegation.accumulated_rewards.clone();
+        let commission = self
+            .validators
+            .iter()
+            .find(|v| v.address.eq(&delegation.delegation.validator))
+            .map(|v| v.commission)
+            .unwrap_or_default();
+        let blocks = self.height.saturating_sub(delegation.start_height);
+        let amount = delegation.delegation.amount.amount
+            * Uint128::from(blocks)
+            * self.reward_rate
+            * (Decimal::one() - commission);
+        if amount.is_zero() {
+            return rewards;
+        }
+        match rewards.iter_mut().find(|c| c.denom.eq(&self.denom)) {
+            Some(coin) => coin.amount += amount,
+            None => rewards.push(Coin {
+                denom: self.denom.to_owned(),
+                amount,
+            }),
+        }
+        rewards
+    }
+
+    fn full_delegation(&self, delegation: &Delegation) -> FullDelegation {
+        FullDelegation {
+            accumulated_rewards: self.rewards(delegation),
+            ..delegation.delegation.clone()
+        }
+    }
+
+    fn position(&self, delegator: &str, validator: &str) -> Option<usize> {
+        self.delegations.iter().position(|d| {
+            d.delegation.delegator.as_str().eq(delegator) && d.delegation.validator.eq(validator)
+        })
+    }
+
+    fn check_delegation(&self, validator: &str, amount: &Coin) -> Result<(), String> {
+        if !self.validators.iter().any(|v| v.address.eq(validator)) {
+            return Err(format!("validator {} not found", validator));
+        }
+        if amount.denom.ne(&self.denom) {
+            return Err(format!(
+                "invalid coin denomination: got {}, expected {}",
+                amount.denom, self.denom
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn withdraw_address(&self, delegator: &str) -> String {
+        self.withdraw_addresses
+            .iter()
+            .find(|(addr, _)| addr.eq(delegator))
+            .map(|(_, withdraw_addr)| withdraw_addr.to_owned())
+            .unwrap_or_else(|| delegator.to_string())
+    }
+
+    pub fn set_withdraw_address(&mut self, delegator: &str, withdraw_addr: &str) {
+        self.withdraw_addresses
+            .retain(|(addr, _)| addr.ne(delegator));
+        self.withdraw_addresses
+            .push((delegator.to_string(), withdraw_addr.to_string()));
+    }
+
+    // withdraw_rewards pays the rewards of a delegation to the withdraw address and restarts accrual
+    pub fn withdraw_rewards(
+        &mut self,
+        bank: &mut Bank,
+        delegator: &str,
+        validator: &str,
+    ) -> Result<Vec<Coin>, String> {
+        let index = match self.position(delegator, validator) {
+            None => return Err(format!("no delegation for ({}, {})", delegator, validator)),
+            Some(index) => index,
+        };
+        let rewards = self.rewards(&self.delegations[index]);
+        bank.mint(&self.withdraw_address(delegator), &rewards);
+        let delegation = &mut self.delegations[index];
+        delegation.delegation.accumulated_rewards = vec![];
+        delegation.start_height = self.height;
+        Ok(rewards)
+    }
+
+    // delegate moves the coins out of the bank, rewards pending on the delegation are paid first
+    pub fn delegate(
+        &mut self,
+        bank: &mut Bank,
+        delegator: &str,
+        validator: &str,
+        amount: &Coin,
+    ) -> Result<(), String> {
+        self.check_delegation(validator, amount)?;
+        bank.burn(delegator, &[amount.to_owned()])?;
+        self.bond(bank, delegator, validator, amount)
+    }
+
+    fn bond(
+        &mut self,
+        bank: &mut Bank,
+        delegator: &str,
+        validator: &str,
+        amount: &Coin,
+    ) -> Result<(), String> {
+        match self.position(delegator, validator) {
+            Some(index) => {
+                self.withdraw_rewards(bank, delegator, validator)?;
+                let delegation = &mut self.delegations[index].delegation;
+                delegation.amount.amount += amount.amount;
+                delegation.can_redelegate.amount += amount.amount;
+            }
+            None => self.delegations.push(Delegation {
+                delegation: FullDelegation {
+                    delegator: Addr::unchecked(delegator),
+                    validator: validator.to_string(),
+                    amount: amount.to_owned(),
+                    can_redelegate: amount.to_owned(),
+                    accumulated_rewards: vec![],
+                },
+                start_height: self.height,
+            }),
+        }
+        Ok(())
+    }
+
+    // unbond reduces a delegation, removing it when nothing is left
+    fn unbond(
+        &mut self,
+        bank: &mut Bank,
+        delegator: &str,
+        validator: &str,
+        amount: &Coin,
+    ) -> Result<(), String> {
+        self.check_delegation(validator, amount)?;
+        let index = match self.position(delegator, validator) {
+            Some(index) if self.delegations[index].delegation.amount.amount >= amount.amount => {
+                index
+            }
+            _ => {
+                return Err(format!(
+                    "insufficient delegation: {} of {} to {}",
+                    amount, delegator, validator
+                ))
+            }
+        };
+        self.withdraw_rewards(bank, delegator, validator)?;
+        let delegation = &mut self.delegations[index].delegation;
+        delegation.amount.amount -= amount.amount;
+        delegation.can_redelegate.amount = delegation
+            .can_redelegate
+            .amount
+            .saturating_sub(amount.amount);
+        if delegation.amount.amount.is_zero() {
+            self.delegations.remove(index);
+        }
+        Ok(())
+    }
+
+    // undelegate returns the height the coins are paid back at
+    pub fn undelegate(
+        &mut self,
+        bank: &mut Bank,
+        delegator: &str,
+        validator: &str,
+        amount: &Coin,
+    ) -> Result<u64, String> {
+        self.unbond(bank, delegator, validator, amount)?;
+        let completion_height = self.height + self.unbonding_blocks;
+        self.unbondings.push(Unbonding {
+            delegator: delegator.to_string(),
+            amount: amount.to_owned(),
+            completion_height,
+        });
+        Ok(completion_height)
+    }
+
+    pub fn redelegate(
+        &mut self,
+        bank: &mut Bank,
+        delegator: &str,
+        src_validator: &str,
+        dst_validator: &str,
+        amount: &Coin,
+    ) -> Result<(), String> {
+        self.check_delegation(dst_validator, amount)?;
+        self.unbond(bank, delegator, src_validator, amount)?;
+        self.bond(bank, delegator, dst_validator, amount)
+    }
+
+    // complete_unbonding pays back the undelegations whose unbonding period is over
+    pub fn complete_unbonding(&mut self, bank: &mut Bank) {
+        let height = self.height;
+        for unbonding in self.unbondings.iter() {
+            if unbonding.completion_height <= height {
+                bank.mint(&unbonding.delegator, &[unbonding.amount.to_owned()]);
+            }
+        }
+        self.unbondings.retain(|u| u.completion_height > height);
+    }
+
+    pub fn query(&self, request: &StakingQuery) -> QuerierResult {
+        let contract_result: ContractResult<Binary> = match request {
+            StakingQuery::Validator { address } => {
+                let validator = self
+                    .validators
+                    .iter()
+                    .find(|v| v.address.eq(address))
+                    .cloned();
+
+                let res = ValidatorResponse { validator };
+                to_binary(&res).into()
+            }
+            StakingQuery::AllValidators {} => {
+                let res = AllValidatorsResponse {
+                    validators: self.validators.clone(),
+                };
+                to_binary(&res).into()
+            }
+            StakingQuery::AllDelegations { delegator } => {
+                let delegations: Vec<_> = self
+                    .delegations
+                    .iter()
+                    .filter(|d| &d.delegation.delegator == delegator)
+                    .map(|d| d.delegation.clone().into())
+                    .collect();
+                let res = AllDelegationsResponse { delegations };
+                to_binary(&res).into()
+            }
+            StakingQuery::Delegation {
+                delegator,
+                validator,
+            } => {
+                let delegation = self
+                    .position(delegator, validator)
+                    .map(|index| self.full_delegation(&self.delegations[index]));
+                let res = DelegationResponse { delegation };
+                to_binary(&res).into()
+            }
+            StakingQuery::BondedDenom {} => {
+                let res = BondedDenomResponse {
+                    denom: self.denom.clone(),
+                };
+                to_binary(&res).into()
+            }
+            _ => {
+                return SystemResult::Err(SystemError::UnsupportedRequest {
+                    kind: "Not implemented".to_string(),
+                })
+            }
+        };
+        // system result is always ok in the mock implementation
+        SystemResult::Ok(contract_result)
+    }
+}
+
+#[test]
+fn test_staking_fixture() {
+    let fixture: StakingFixture = cosmwasm_std::from_slice(
+        br#"{"delegations":[{"delegator":"alice","validator":"val","amount":{"denom":"orai","amount":"100"}}]}"#,
+    )
+    .unwrap();
+    let staking = StakingQuerier::from_fixture("orai", 1, fixture);
+    assert_eq!(staking.denom, "orai");
+    assert_eq!(
+        staking.delegations[0].delegation.can_redelegate,
+        Coin::new(100, "orai")
+    );
+}
+
+#[test]
+fn test_staking_rewards() {
+    let validator = Validator {
+        address: "val".to_string(),
+        commission: Decimal::zero(),
+        max_commission: Decimal::one(),
+        max_change_rate: Decimal::one(),
+    };
+    let mut staking = StakingQuerier::new("orai", 100, &[validator], &[]);
+    staking.reward_rate = Decimal::percent(1);
+    staking.unbonding_blocks = 2;
+    let mut bank = Bank::new(&[("alice", &[Coin::new(1000, "orai")])]);
+
+    staking
+        .delegate(&mut bank, "alice", "val", &Coin::new(1000, "orai"))
+        .unwrap();
+    assert_eq!(bank.balance("alice", "orai"), Uint128::zero());
+
+    // 1% of 1000 for every block
+    let mut delegation = staking.delegations[0].clone();
+    delegation.start_height -= 3;
+    assert_eq!(staking.rewards(&delegation), vec![Coin::new(30, "orai")]);
+
+    staking
+        .undelegate(&mut bank, "alice", "val", &Coin::new(400, "orai"))
+        .unwrap();
+    assert_eq!(
+        staking.delegations[0].delegation.amount,
+        Coin::new(600, "orai")
+    );
+    staking.unbondings[0].completion_height -= 2;
+    staking.complete_unbonding(&mut bank);
+    assert_eq!(bank.balance("alice", "orai"), Uint128::from(400u64));
+}
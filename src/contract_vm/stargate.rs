@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use cosmwasm_std::{
+    Binary, ContractResult, QuerierResult, SubMsgResponse, SystemError, SystemResult,
+};
+use prost_reflect::prost::Message;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor, SerializeOptions};
+use serde_json::Value;
+
+use crate::contract_vm::simulator::{Chain, Simulator};
+
+// handlers read and write protobuf messages as json, with the proto field names
+pub type StargateQueryHandler = fn(&Chain, &Value) -> Result<Value, String>;
+pub type StargateMsgHandler = fn(&Simulator, &str, &Value) -> Result<SubMsgResponse, String>;
+
+/// Stargate decodes protobuf queries and messages with the descriptor sets given on the
+/// command line, then runs the handler registered for their path
+#[derive(Default)]
+pub struct Stargate {
+    pool: DescriptorPool,
+    queries: HashMap<String, StargateQueryHandler>,
+    msgs: HashMap<String, StargateMsgHandler>,
+}
+
+impl Stargate {
+    // add_descriptor_set loads a FileDescriptorSet, like protoc --descriptor_set_out writes
+    pub fn add_descriptor_set(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.pool
+            .decode_file_descriptor_set(bytes)
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn register_query(&mut self, path: &str, handler: StargateQueryHandler) {
+        self.queries.insert(path.to_string(), handler);
+    }
+
+    pub fn register_msg(&mut self, type_url: &str, handler: StargateMsgHandler) {
+        self.msgs.insert(type_url.to_string(), handler);
+    }
+
+    // method returns the request and response types of a query path, /package.Service/Method
+    fn method(&self, path: &str) -> Option<(MessageDescriptor, MessageDescriptor)> {
+        let (service, method) = path.trim_start_matches('/').rsplit_once('/')?;
+        let service = self.pool.get_service_by_name(service)?;
+        let method = service.methods().find(|m| m.name().eq(method))?;
+        Some((method.input(), method.output()))
+    }
+
+    pub fn query(&self, chain: &Chain, path: &str, data: &[u8]) -> QuerierResult {
+        let (handler, (input, output)) = match (self.queries.get(path), self.method(path)) {
+            (Some(handler), Some(method)) => (handler, method),
+            _ => {
+                return SystemResult::Err(SystemError::UnsupportedRequest {
+                    kind: format!("unknown stargate path {}", path),
+                })
+            }
+        };
+        let request = match decode(input, data) {
+            Ok(request) => request,
+            Err(err) => {
+                return SystemResult::Err(SystemError::InvalidRequest {
+                    error: err,
+                    request: Binary::from(data),
+                })
+            }
+        };
+        let result = handler(chain, &request).and_then(|response| encode(output, response));
+        SystemResult::Ok(ContractResult::from(result))
+    }
+
+    // decode_msg returns the handler of a message along with the decoded message,
+    // the handler runs once the chain is no longer borrowed
+    pub fn decode_msg(
+        &self,
+        type_url: &str,
+        value: &[u8],
+    ) -> Result<(StargateMsgHandler, Value), String> {
+        let unknown = || format!("unknown stargate message {}", type_url);
+        let handler = self.msgs.get(type_url).ok_or_else(unknown)?;
+        let desc = self
+            .pool
+            .get_message_by_name(type_url.trim_start_matches('/'))
+            .ok_or_else(unknown)?;
+        Ok((*handler, decode(desc, value)?))
+    }
+}
+
+fn decode(desc: MessageDescriptor, data: &[u8]) -> Result<Value, String> {
+    let msg = DynamicMessage::decode(desc, data).map_err(|e| e.to_string())?;
+    let options = SerializeOptions::new()
+        .use_proto_field_name(true)
+        .skip_default_fields(false);
+    msg.serialize_with_options(serde_json::value::Serializer, &options)
+        .map_err(|e| e.to_string())
+}
+
+fn encode(desc: MessageDescriptor, value: Value) -> Result<Binary, String> {
+    let msg = DynamicMessage::deserialize(desc, value).map_err(|e| e.to_string())?;
+    Ok(Binary::from(msg.encode_to_vec()))
+}
+
+#[test]
+fn test_stargate_query() {
+    use prost_reflect::prost_types::{
+        field_descriptor_proto::{Label, Type},
+        DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+        MethodDescriptorProto, ServiceDescriptorProto,
+    };
+    use serde_json::json;
+
+    let field = |name: &str, number| FieldDescriptorProto {
+        name: Some(name.to_string()),
+        number: Some(number),
+        label: Some(Label::Optional as i32),
+        r#type: Some(Type::String as i32),
+        ..Default::default()
+    };
+    let file = FileDescriptorProto {
+        name: Some("echo.proto".to_string()),
+        package: Some("echo".to_string()),
+        syntax: Some("proto3".to_string()),
+        message_type: vec![DescriptorProto {
+            name: Some("Echo".to_string()),
+            field: vec![field("text", 1)],
+            ..Default::default()
+        }],
+        service: vec![ServiceDescriptorProto {
+            name: Some("Query".to_string()),
+            method: vec![MethodDescriptorProto {
+                name: Some("Echo".to_string()),
+                input_type: Some(".echo.Echo".to_string()),
+                output_type: Some(".echo.Echo".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let set = FileDescriptorSet { file: vec![file] };
+
+    let mut stargate = Stargate::default();
+    stargate.add_descriptor_set(&set.encode_to_vec()).unwrap();
+    stargate.register_query("/echo.Query/Echo", |_, request| {
+        Ok(json!({ "text": format!("{}!", request["text"].as_str().unwrap()) }))
+    });
+
+    let desc = stargate.pool.get_message_by_name("echo.Echo").unwrap();
+    let request = encode(desc.clone(), json!({ "text": "hi" })).unwrap();
+    let chain = Chain::new();
+    let response = match stargate.query(&chain, "/echo.Query/Echo", &request) {
+        SystemResult::Ok(ContractResult::Ok(response)) => response,
+        result => panic!("unexpected result {:?}", result),
+    };
+    assert_eq!(decode(desc, &response).unwrap(), json!({ "text": "hi!" }));
+
+    // unregistered paths are errors, not panics
+    assert!(matches!(
+        stargate.query(&chain, "/echo.Query/Missing", &request),
+        SystemResult::Err(SystemError::UnsupportedRequest { .. })
+    ));
+}
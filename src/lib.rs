@@ -0,0 +1,3 @@
+pub mod contract_vm;
+
+pub use crate::contract_vm::simulator::{AppResponse, Simulator};
@@ -1,83 +1,36 @@
-pub mod contract_vm;
-
 extern crate clap;
 
-use crate::contract_vm::analyzer::{Member, INDENT};
-use crate::contract_vm::editor::TerminalEditor;
-use crate::contract_vm::engine::{ContractInstance, BLOCK_HEIGHT, CHAIN_ID, DENOM};
-use crate::contract_vm::mock::MockStorage;
-use crate::contract_vm::querier::WasmHandler;
+use cosmwasm_simulate::contract_vm::analyzer::{Analyzer, Member, INDENT};
+use cosmwasm_simulate::contract_vm::editor::TerminalEditor;
+use cosmwasm_simulate::contract_vm::engine::{ContractInstance, DENOM};
+use cosmwasm_simulate::contract_vm::ibc::Relayer;
+use cosmwasm_simulate::contract_vm::mock::{SpecialMsgFixture, SpecialQueryFixture};
+use cosmwasm_simulate::contract_vm::script::{bank_command, block_command, Script};
+use cosmwasm_simulate::contract_vm::simulator::{coins_to_string, parse_coins, Chain};
+use cosmwasm_simulate::contract_vm::staking::{StakingFixture, StakingQuerier};
+use cosmwasm_simulate::{AppResponse, Simulator};
 
 use clap::{App, Arg};
 use colored::*;
-use cosmwasm_std::{
-    from_slice, Addr, Attribute, Binary, Coin, CosmosMsg, MessageInfo, QuerierResult, SystemError,
-    SystemResult, Uint128, WasmMsg, WasmQuery,
-};
+use cosmwasm_std::{from_slice, Addr, Binary, Coin, IbcOrder, MessageInfo, Timestamp, Uint128};
 use itertools::sorted;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::io::{Error, ErrorKind};
-use std::mem::transmute;
 use std::path::Path;
-use std::{fs, sync, thread, time, vec};
+use std::sync::mpsc::{Receiver, Sender};
+use std::{fs, process, sync, thread, time, vec};
 
 // default const is 'static lifetime
 const DEFAULT_SENDER_ADDR: &str = "fake_sender_addr";
 const DEFAULT_SENDER_BALANCE: u64 = 10_000_000_000_000_000;
 
-struct Config {
+// Repl is the terminal client of the simulator
+struct Repl {
+    simulator: Simulator,
     editor: TerminalEditor,
-    engines: HashMap<String, ContractInstance>,
-    accounts: Vec<MessageInfo>,
-}
-// using raw pointer with a life time to store static object
-static mut _DATA: *const Config = 0 as *const Config;
-impl Config {
-    unsafe fn get<'a>() -> &'a mut Config {
-        if _DATA.is_null() {
-            _DATA = transmute(Box::new(Config {
-                editor: TerminalEditor::new(),
-                engines: HashMap::new(),
-                accounts: Vec::new(),
-            }));
-        }
-        return transmute(_DATA);
-    }
-}
-
-fn query_wasm(request: &WasmQuery) -> QuerierResult {
-    unsafe {
-        let Config { engines, .. } = Config::get();
-        match request {
-            WasmQuery::Smart { contract_addr, msg } => {
-                match engines.get_mut(contract_addr.as_str()) {
-                    None => SystemResult::Err(SystemError::NoSuchContract {
-                        addr: contract_addr.to_owned(),
-                    }),
-                    Some(engine) => {
-                        let result = cosmwasm_vm::call_query(
-                            &mut engine.instance,
-                            &engine.env,
-                            msg.as_slice(),
-                        );
-
-                        // response can not unwrap, so it is empty
-                        match result {
-                            Ok(response) => SystemResult::Ok(response),
-                            Err(err) => SystemResult::Err(SystemError::InvalidResponse {
-                                error: err.to_string(),
-                                response: Binary::from([]),
-                            }),
-                        }
-                    }
-                }
-            }
-            _ => SystemResult::Err(SystemError::UnsupportedRequest {
-                kind: "Not implemented".to_string(),
-            }),
-        }
-    }
+    // wasm files changed on disk with the contract running them, reloaded before the next prompt
+    reloads: Receiver<(String, String)>,
 }
 
 fn check_is_need_slash(name: &str) -> bool {
@@ -88,17 +41,19 @@ fn check_is_need_slash(name: &str) -> bool {
     return false;
 }
 
-fn to_json_item(name: &String, type_name: &str, engine: &ContractInstance) -> String {
+fn to_json_item(
+    name: &String,
+    type_name: &str,
+    editor: &mut TerminalEditor,
+    analyzer: &Analyzer,
+) -> String {
     let (strip_type_name, optional) = match type_name.strip_suffix('?') {
         Some(s) => (s, true),
         None => (type_name, false),
     };
     let mut data: String = String::new();
 
-    unsafe {
-        let Config { editor, .. } = Config::get();
-        editor.readline(&mut data, true);
-    }
+    editor.readline(&mut data, true);
 
     // do not append optional when empty
     if data.is_empty() && optional {
@@ -110,7 +65,7 @@ fn to_json_item(name: &String, type_name: &str, engine: &ContractInstance) -> St
         data = Binary::from(data.as_bytes()).to_base64();
     }
 
-    let mapped_type_name = match engine.analyzer.map_of_basetype.get(strip_type_name) {
+    let mapped_type_name = match analyzer.map_of_basetype.get(strip_type_name) {
         None => strip_type_name,
         Some(v) => v,
     };
@@ -130,13 +85,18 @@ fn to_json_item(name: &String, type_name: &str, engine: &ContractInstance) -> St
     return params;
 }
 
-fn input_type(mem_name: &String, type_name: &String, engine: &ContractInstance) -> String {
+fn input_type(
+    mem_name: &String,
+    type_name: &String,
+    editor: &mut TerminalEditor,
+    analyzer: &Analyzer,
+) -> String {
     println!("input [{}]:", mem_name.blue().bold());
-    let st = match engine.analyzer.map_of_struct.get_key_value(type_name) {
+    let st = match analyzer.map_of_struct.get_key_value(type_name) {
         Some(h) => h,
         _ => {
             // return to function, not return to st
-            return to_json_item(&mem_name, &type_name, engine);
+            return to_json_item(&mem_name, &type_name, editor, analyzer);
         }
     };
     //todo:need show all members by recursive invocation
@@ -145,10 +105,7 @@ fn input_type(mem_name: &String, type_name: &String, engine: &ContractInstance)
     params.push_str("\":");
 
     if st.1.len() == 0 {
-        unsafe {
-            let Config { editor, .. } = Config::get();
-            editor.readline(&mut params, true);
-        }
+        editor.readline(&mut params, true);
     } else {
         params.push('{');
         // member is default sorted
@@ -159,7 +116,7 @@ fn input_type(mem_name: &String, type_name: &String, engine: &ContractInstance)
                 members.0.blue().bold(),
                 members.1.yellow()
             );
-            params.push_str(to_json_item(&members.0, members.1, engine).as_str());
+            params.push_str(to_json_item(&members.0, members.1, editor, analyzer).as_str());
         }
         // remove last , character
         if st.1.len() > 0 {
@@ -176,7 +133,8 @@ fn input_type(mem_name: &String, type_name: &String, engine: &ContractInstance)
 fn input_message(
     name: &str,
     members: &Vec<Member>,
-    engine: &ContractInstance,
+    editor: &mut TerminalEditor,
+    analyzer: &Analyzer,
     is_enum: &bool,
 ) -> String {
     let mut final_msg: String = "{".to_string();
@@ -188,8 +146,15 @@ fn input_message(
     }
     let mut option_values: String = String::new();
     for vcm in members {
-        option_values
-            .push_str(input_type(&vcm.member_name, &vcm.member_def.to_string(), engine).as_str());
+        option_values.push_str(
+            input_type(
+                &vcm.member_name,
+                &vcm.member_def.to_string(),
+                editor,
+                analyzer,
+            )
+            .as_str(),
+        );
     }
 
     // if there is option value then push to msg
@@ -208,352 +173,650 @@ fn input_message(
 }
 
 // get_call_type return value and indicate it is contract switch or account switch
-fn get_call_type() -> Option<(String, bool, bool)> {
+fn get_call_type(repl: &mut Repl) -> Option<(String, bool, bool)> {
+    let Repl {
+        simulator, editor, ..
+    } = repl;
+    let chain = simulator.chain();
     let mut call_type = String::new();
     let mut params = vec![
         "instantiate".to_string(),
         "execute".to_string(),
         "query".to_string(),
+        "migrate".to_string(),
+        "sudo".to_string(),
     ];
     let mut contract_switch = false;
-    let mut account_switch = false;
 
     print!(
-        "Input call type ({} | {} | {}",
+        "Input call type ({} | {} | {} | {} | {}",
         "instantiate".green().bold(),
         "execute".green().bold(),
         "query".green().bold(),
+        "migrate".green().bold(),
+        "sudo".green().bold(),
     );
-    unsafe {
-        let Config {
-            engines,
-            accounts,
-            editor,
-        } = Config::get();
-        if engines.len() > 1 {
-            contract_switch = true;
-        }
-        if accounts.len() > 1 {
-            account_switch = true;
-        }
+    if chain.engines.len() > 1 {
+        contract_switch = true;
+    }
+    if contract_switch {
+        print!(" | {}", "contract".blue().bold());
+        params.push("contract".to_string());
+        // channels are opened between two contracts
+        print!(" | {}", "ibc".blue().bold());
+        params.push("ibc".to_string());
+    }
+    // calls can be sent from any address, not only the known accounts
+    print!(" | {}", "account".blue().bold());
+    params.push("account".to_string());
+    // height, time and chain id can be changed at any time, as well as the balances
+    print!(" | {}", "block".blue().bold());
+    params.push("block".to_string());
+    print!(" | {}", "bank".blue().bold());
+    params.push("bank".to_string());
+
+    // clone params to use contains without moving problem
+    editor.update_history_entries(params.clone());
+
+    println!(")");
+
+    editor.readline(&mut call_type, false);
+
+    if !params.contains(&call_type) {
+        print!(
+            "Wrong call type [{}], must one of ({} | {} | {} | {} | {}",
+            call_type.red().bold(),
+            "instantiate".green().bold(),
+            "execute".green().bold(),
+            "query".green().bold(),
+            "migrate".green().bold(),
+            "sudo".green().bold(),
+        );
         if contract_switch {
-            print!(" | {}", "contract".blue().bold());
-            params.push("contract".to_string());
+            print!(" | {}", "contract".green().bold());
+            print!(" | {}", "ibc".green().bold());
         }
-        if account_switch {
-            print!(" | {}", "account".blue().bold());
-            params.push("account".to_string());
+        print!(" | {}", "account".green().bold());
+        print!(" | {}", "block".green().bold());
+        print!(" | {}", "bank".green().bold());
+        println!(")");
+        return None;
+    }
+
+    // default messages
+    if contract_switch && call_type.eq("contract") {
+        let mut first = true;
+        let mut call_param = String::new();
+        print!("Choose smart contract [ ");
+
+        editor.clear_history();
+
+        for k in sorted(chain.engines.keys()) {
+            if first {
+                first = false;
+            } else {
+                print!(" | ")
+            }
+            print!("{}", k.green().bold());
+            // show code id and label of contracts created at runtime
+            if let Some(meta) = chain.contracts.get(k) {
+                if meta.label.ne(k) {
+                    print!(" ({} code {})", meta.label, meta.code_id);
+                }
+            }
+            editor.add_history_entry(k);
+            if let Some(alias) = chain.alias(k) {
+                editor.add_history_entry(alias);
+            }
         }
 
-        // clone params to use contains without moving problem
-        editor.update_history_entries(params.clone());
+        print!(" ]\n");
 
-        println!(")");
+        editor.readline(&mut call_param, false);
+        let call_param = chain.resolve(&call_param);
 
-        editor.readline(&mut call_type, false);
+        // check contract existed
+        if chain.engines.get(&call_param).is_none() {
+            println!("Smart contract {} not existed", call_param.red().bold());
+            return None;
+        }
 
-        if !params.contains(&call_type) {
-            print!(
-                "Wrong call type [{}], must one of ({} | {} | {}",
-                call_type.red().bold(),
-                "instantiate".green().bold(),
-                "execute".green().bold(),
-                "query".green().bold(),
-            );
-            if contract_switch {
-                print!(" | {}", "contract".green().bold());
+        // return contract as switch param
+        return Some((call_param, true, false));
+    } else if call_type.eq("account") {
+        let mut first = true;
+        let mut call_param = String::new();
+        print!("Choose account [ ");
+
+        editor.clear_history();
+
+        for account in chain.accounts.iter() {
+            if first {
+                first = false;
+            } else {
+                print!(" | ")
             }
-            if account_switch {
-                print!(" | {}", "contract".green().bold());
+            print!("{}", with_alias(&chain, account).green().bold());
+            editor.add_history_entry(account);
+            if let Some(alias) = chain.alias(account) {
+                editor.add_history_entry(alias);
             }
-            println!(")");
+        }
+
+        print!(" ]\n");
+
+        editor.readline(&mut call_param, false);
+
+        if call_param.is_empty() {
+            println!("{}", "Empty account".red().bold());
             return None;
         }
+        // a new name is given an address
+        drop(chain);
+        let call_param = simulator.add_alias(&call_param);
+        let chain = simulator.chain();
+        // an unknown address is impersonated, it only owns what it is sent
+        if !chain.accounts.contains(&call_param) {
+            println!(
+                "Sending as {}",
+                with_alias(&chain, &call_param).yellow().bold()
+            );
+        }
 
-        // default messages
-        if contract_switch && call_type.eq("contract") {
-            let mut first = true;
-            let mut call_param = String::new();
-            print!("Choose smart contract [ ");
+        // return contract as switch param
+        return Some((call_param, false, true));
+    }
 
-            editor.clear_history();
+    return Some((call_type, false, false));
+}
 
-            for k in sorted(engines.keys()) {
-                if first {
-                    first = false;
-                } else {
-                    print!(" | ")
-                }
-                print!("{}", k.green().bold());
-                editor.add_history_entry(k);
-            }
+// get_code_id asks for a stored code, used by migrate
+fn get_code_id(repl: &mut Repl) -> Option<u64> {
+    let Repl {
+        simulator, editor, ..
+    } = repl;
+    let codes = &simulator.chain().codes;
+    let mut first = true;
+    let mut call_param = String::new();
+    print!("Choose code id [ ");
+
+    editor.clear_history();
+
+    for (index, file) in codes.iter().enumerate() {
+        if first {
+            first = false;
+        } else {
+            print!(" | ")
+        }
+        let code_id = (index + 1).to_string();
+        print!("{} ({})", code_id.green().bold(), file);
+        editor.add_history_entry(&code_id);
+    }
 
-            print!(" ]\n");
+    print!(" ]\n");
 
-            editor.readline(&mut call_param, false);
+    editor.readline(&mut call_param, false);
 
-            // check contract existed
-            if engines.get(&call_param).is_none() {
-                println!("Smart contract {} not existed", call_param.red().bold());
-                return None;
-            }
+    match call_param.parse::<u64>() {
+        Ok(code_id) if code_id > 0 && code_id as usize <= codes.len() => Some(code_id),
+        _ => {
+            println!("Code id {} not existed", call_param.red().bold());
+            None
+        }
+    }
+}
 
-            // return contract as switch param
-            return Some((call_param, true, false));
-        } else if account_switch && call_type.eq("account") {
-            let mut first = true;
-            let mut call_param = String::new();
-            print!("Choose account [ ");
+// get_funds asks for the coins attached to a call, the sender pays exactly those
+fn get_funds(repl: &mut Repl, sender_addr: &str) -> Option<Vec<Coin>> {
+    let Repl {
+        simulator, editor, ..
+    } = repl;
+    let balances = simulator.chain().bank.all_balances(sender_addr);
+    println!(
+        "Input funds like 100{} (empty for none), {} owns [ {} ]",
+        DENOM,
+        sender_addr.green().bold(),
+        coins_to_string(&balances).green().bold()
+    );
 
-            editor.clear_history();
+    editor.update_history_entries(vec![coins_to_string(&balances)]);
+    let mut funds = String::new();
+    editor.readline(&mut funds, false);
 
-            for info in accounts.iter() {
-                if first {
-                    first = false;
-                } else {
-                    print!(" | ")
+    match parse_coins(&funds) {
+        Ok(funds) => Some(funds),
+        Err(err) => {
+            println!("{}", err.red());
+            None
+        }
+    }
+}
+
+// call_engine runs the call from the terminal, the simulator discards every change
+// made by the message tree when any message fails, migrate asks for the new code first
+fn call_engine(
+    repl: &mut Repl,
+    contract_addr: &str,
+    call_type: &str,
+    json_msg: &str,
+    sender_addr: &str,
+) {
+    let code_id = match call_type {
+        "migrate" => match get_code_id(repl) {
+            None => return,
+            code_id => code_id,
+        },
+        _ => None,
+    };
+    let funds = match call_type {
+        "instantiate" | "execute" => match get_funds(repl, sender_addr) {
+            None => return,
+            Some(funds) => funds,
+        },
+        _ => vec![],
+    };
+    let info = &MessageInfo {
+        sender: Addr::unchecked(sender_addr),
+        funds,
+    };
+    let Repl {
+        simulator, editor, ..
+    } = repl;
+
+    println!();
+    println!("===========================call started===========================");
+    println!(
+        "executing func [{}] , params is {}",
+        call_type.green().bold(),
+        json_msg.yellow()
+    );
+    let msg = json_msg.as_bytes();
+    let result = match call_type {
+        "instantiate" => simulator.instantiate_raw(contract_addr, msg, info),
+        "execute" => simulator.execute_raw(contract_addr, msg, info),
+        "migrate" => {
+            simulator.migrate_raw(contract_addr, sender_addr, code_id.unwrap_or_default(), msg)
+        }
+        "sudo" => simulator.sudo_raw(contract_addr, msg),
+        "query" => {
+            // queries do not run in a block, only their gas is reported
+            let gas_init = simulator.gas_left(contract_addr);
+            simulator.query_raw(contract_addr, msg).map(|data| {
+                ContractInstance::dump_result("query data", data.as_slice(), 10);
+                AppResponse {
+                    gas_used: gas_init.saturating_sub(simulator.gas_left(contract_addr)),
+                    ..AppResponse::default()
+                }
+            })
+        }
+        _ => Err(format!("wrong dispatcher call {}", call_type)),
+    };
+
+    match &result {
+        Ok(response) => {
+            ContractInstance::dump_events(&response.events, response.data.as_ref());
+            // contracts created by the call can be picked from the history
+            for event in response.events.iter().filter(|e| e.ty.eq("instantiate")) {
+                for attr in event.attributes.iter() {
+                    if attr.key.eq("_contract_address") {
+                        editor.add_input_history_entry(attr.value.to_owned());
+                    }
                 }
-                print!("{}", info.sender.as_str().green().bold());
-                editor.add_history_entry(info.sender.as_str());
             }
+            println!(
+                "{}   : {}",
+                "gas used".blue().bold(),
+                response.gas_used.to_string().yellow()
+            );
+        }
+        Err(err) => println!("{}", err.red()),
+    }
+    println!("===========================call finished===========================");
+    println!();
 
-            print!(" ]\n");
+    if result.is_err() && call_type.ne("query") {
+        println!("{}", "Transaction rolled back".red().bold());
+    }
+}
 
-            editor.readline(&mut call_param, false);
+// apply_reloads compiles again the contracts whose wasm file changed on disk, keeping their storage
+fn apply_reloads(repl: &Repl) {
+    while let Ok((wasm_file, contract_addr)) = repl.reloads.try_recv() {
+        if let Err(e) = repl.simulator.reload_contract(&wasm_file, &contract_addr) {
+            println!("error occurred during install contract: {}", e.red());
+        }
+    }
+}
 
-            // check account existed
-            if !accounts
-                .iter()
-                .map(|x| x.sender.to_string())
-                .collect::<Vec<String>>()
-                .contains(&call_param)
-            {
-                println!("Account {} not existed", call_param.red().bold());
-                return None;
-            }
+// with_alias shows the name of an address next to it
+fn with_alias(chain: &Chain, addr: &str) -> String {
+    match chain.alias(addr) {
+        Some(alias) => format!("{} ({})", addr, alias),
+        None => addr.to_string(),
+    }
+}
 
-            // return contract as switch param
-            return Some((call_param, false, true));
+fn print_banner(repl: &Repl, contract_addr: &str, sender_addr: &str) {
+    let chain = repl.simulator.chain();
+    let block = &chain.block;
+    println!(
+        "Start_simulate with sender: {}, contract: {}, chain: {}, denom: {}, block height: {}, time: {}",
+        with_alias(&chain, sender_addr).green().bold(),
+        with_alias(&chain, contract_addr).green().bold(),
+        block.chain_id.green().bold(),
+        DENOM.green().bold(),
+        block.height.to_string().green().bold(),
+        block.time.seconds().to_string().green().bold()
+    );
+}
+
+// simulate_block changes the block of the chain, moving forward ends every block on the way
+// so that expirations, vesting and unbondings can be tested. Without auto commit the calls
+// stay in the current block until it is committed
+fn simulate_block(repl: &mut Repl) {
+    let Repl {
+        simulator, editor, ..
+    } = repl;
+    println!(
+        "Input block command ({} | {} | {} | {} | {} | {} | {}):",
+        "commit".green().bold(),
+        "auto on|off".green().bold(),
+        "height N".green().bold(),
+        "time SECONDS".green().bold(),
+        "chain-id ID".green().bold(),
+        "+N blocks".green().bold(),
+        "+DURATION like 30s, 5m, 2h, 1d".green().bold()
+    );
+    editor.update_history_entries(vec![
+        "commit".to_string(),
+        "+1".to_string(),
+        "+1d".to_string(),
+    ]);
+    let mut command = String::new();
+    editor.readline(&mut command, false);
+
+    match block_command(simulator, &command) {
+        Ok(()) => {
+            let chain = simulator.chain();
+            println!(
+                "{} height: {}, time: {}, chain: {}, txs: {}, auto commit: {}",
+                "[block]".green().bold(),
+                chain.block.height.to_string().yellow(),
+                chain.block.time.seconds().to_string().yellow(),
+                chain.block.chain_id.yellow(),
+                chain.tx_index.to_string().yellow(),
+                chain.auto_commit.to_string().yellow()
+            );
         }
+        Err(err) => println!("{}", err.red()),
     }
+}
 
-    return Some((call_type, false, false));
+// dump_accounts lists the balances of the known accounts and of every other address holding coins
+fn dump_accounts(simulator: &Simulator) {
+    let chain = simulator.chain();
+    let mut addresses = chain.bank.addresses();
+    addresses.extend(chain.accounts.iter().cloned());
+    addresses.sort();
+    addresses.dedup();
+
+    println!("{}", "[accounts]".green().bold());
+    let len = addresses
+        .iter()
+        .map(|a| with_alias(&chain, a).len())
+        .max()
+        .unwrap_or_default();
+    for addr in addresses.iter() {
+        let balances = chain.bank.all_balances(addr);
+        let name = with_alias(&chain, addr);
+        ContractInstance::dump_result(&name, coins_to_string(&balances).as_bytes(), len);
+    }
+}
+
+// simulate_bank manages the accounts at runtime, coins are written like 100orai,5usdt
+fn simulate_bank(repl: &mut Repl) {
+    let Repl {
+        simulator, editor, ..
+    } = repl;
+    println!(
+        "Input bank command ({} | {} | {} | {} | {}):",
+        "accounts".green().bold(),
+        "add ADDR [COINS]".green().bold(),
+        "mint ADDR COINS".green().bold(),
+        "burn ADDR COINS".green().bold(),
+        "set ADDR COINS".green().bold()
+    );
+    editor.update_history_entries(vec!["accounts".to_string()]);
+    let mut command = String::new();
+    editor.readline(&mut command, false);
+
+    match bank_command(simulator, &command) {
+        Ok(()) => dump_accounts(simulator),
+        Err(err) => println!("{}", err.red()),
+    }
 }
 
 fn simulate_by_auto_analyze(
-    engine: &mut ContractInstance,
+    repl: &mut Repl,
+    contract_addr: &str,
     sender_addr: &str,
 ) -> Result<(bool, String, String), String> {
-    // enable debug, show info
-    if cfg!(debug_assertions) {
-        engine.analyzer.dump_all_members();
-        engine.analyzer.dump_all_definitions();
-    }
-
-    unsafe {
-        let Config {
-            accounts, editor, ..
-        } = Config::get();
-        let info = match accounts.iter().find(|x| x.sender.as_str().eq(sender_addr)) {
-            Some(i) => i,
-            None => return Err(format!("No account found: {}", sender_addr)),
-        };
+    loop {
+        apply_reloads(repl);
+        // schema may change with the code
+        let analyzer = repl.simulator.analyzer(contract_addr)?;
+
+        // enable debug, show info
+        if cfg!(debug_assertions) {
+            analyzer.dump_all_members();
+            analyzer.dump_all_definitions();
+        }
 
-        loop {
-            println!(
-                "Start_simulate with sender: {}, contract: {}, chain: {}, denom: {}, block height: {}",
-                sender_addr.green().bold(),
-                engine.env.contract.address.to_string().green().bold(),
-                CHAIN_ID.green().bold(), DENOM.green().bold(), BLOCK_HEIGHT.to_string().green().bold()
-            );
+        print_banner(repl, contract_addr, sender_addr);
 
-            let (call_type, contract_switch, account_switch) = match get_call_type() {
-                None => continue,
-                Some(s) => s,
-            };
+        let (call_type, contract_switch, account_switch) = match get_call_type(repl) {
+            None => continue,
+            Some(s) => s,
+        };
 
-            let mut call_param = String::new();
-            let mut first = true;
-            // default messages
-            if contract_switch {
-                // change contract
-                if engine.env.contract.address.to_string().ne(&call_type) {
-                    return Ok((true, call_type, sender_addr.to_string()));
+        let mut call_param = String::new();
+        let mut first = true;
+        // default messages
+        if contract_switch {
+            // change contract
+            if contract_addr.ne(&call_type) {
+                return Ok((true, call_type, sender_addr.to_string()));
+            }
+            continue;
+        } else if account_switch {
+            // change account
+            if sender_addr.ne(call_type.as_str()) {
+                return Ok((true, contract_addr.to_string(), call_type));
+            }
+            continue;
+        } else if call_type.eq("ibc") {
+            simulate_ibc(repl, contract_addr);
+            continue;
+        } else if call_type.eq("block") {
+            simulate_block(repl);
+            continue;
+        } else if call_type.eq("bank") {
+            simulate_bank(repl);
+            continue;
+        } else if call_type.eq("instantiate")
+            && analyzer.map_of_member.contains_key("InstantiateMsg")
+        {
+            call_param = "InstantiateMsg".to_string();
+        } else if call_type.eq("execute") && analyzer.map_of_member.contains_key("ExecuteMsg") {
+            call_param = "ExecuteMsg".to_string();
+        } else if call_type.eq("query") && analyzer.map_of_member.contains_key("QueryMsg") {
+            call_param = "QueryMsg".to_string();
+        } else if call_type.eq("migrate") && analyzer.map_of_member.contains_key("MigrateMsg") {
+            call_param = "MigrateMsg".to_string();
+        } else if call_type.eq("sudo") && analyzer.map_of_member.contains_key("SudoMsg") {
+            call_param = "SudoMsg".to_string();
+        } else {
+            print!("Input Call param from [ ");
+
+            repl.editor.clear_history();
+
+            for k in sorted(analyzer.map_of_member.keys()) {
+                if first {
+                    first = false;
+                } else {
+                    print!(" | ")
                 }
-                continue;
-            } else if account_switch {
-                // change account
-                if sender_addr.ne(call_type.as_str()) {
-                    return Ok((true, engine.env.contract.address.to_string(), call_type));
+                print!("{}", k.green().bold());
+                repl.editor.add_history_entry(k);
+            }
+
+            print!(" ]\n");
+
+            repl.editor.readline(&mut call_param, false);
+        }
+
+        // if there is anyOf, it is enum
+        let is_enum = analyzer.map_of_enum.get(&call_param).unwrap_or(&false);
+
+        let msg_type: &HashMap<String, Vec<Member>> =
+            match analyzer.map_of_member.get(call_param.as_str()) {
+                None => {
+                    println!("can not find msg type {}", call_param.as_str());
+                    continue;
                 }
-                continue;
-            } else if call_type.eq("instantiate")
-                && engine.analyzer.map_of_member.contains_key("InstantiateMsg")
-            {
-                call_param = "InstantiateMsg".to_string();
-            } else if call_type.eq("execute")
-                && engine.analyzer.map_of_member.contains_key("ExecuteMsg")
-            {
-                call_param = "ExecuteMsg".to_string();
-            } else if call_type.eq("query")
-                && engine.analyzer.map_of_member.contains_key("QueryMsg")
-            {
-                call_param = "QueryMsg".to_string();
+                Some(v) => v,
+            };
+        let len = msg_type.len();
+        if len > 0 {
+            //only one msg
+            if msg_type.len() == 1 {
+                call_param = msg_type.keys().next().unwrap().to_string();
             } else {
                 print!("Input Call param from [ ");
+                first = true;
 
-                editor.clear_history();
-
-                for k in sorted(engine.analyzer.map_of_member.keys()) {
+                repl.editor.clear_history();
+                for k in sorted(msg_type.keys()) {
                     if first {
                         first = false;
                     } else {
                         print!(" | ")
                     }
                     print!("{}", k.green().bold());
-                    editor.add_history_entry(k);
+                    repl.editor.add_history_entry(k);
                 }
 
                 print!(" ]\n");
+                call_param.clear();
 
-                editor.readline(&mut call_param, false);
+                repl.editor.readline(&mut call_param, false);
             }
+        }
 
-            // if there is anyOf, it is enum
-            let is_enum = engine
-                .analyzer
-                .map_of_enum
-                .get(&call_param)
-                .unwrap_or(&false);
-
-            let msg_type: &HashMap<String, Vec<Member>> =
-                match engine.analyzer.map_of_member.get(call_param.as_str()) {
-                    None => {
-                        println!("can not find msg type {}", call_param.as_str());
-                        continue;
-                    }
-                    Some(v) => v,
-                };
-            let len = msg_type.len();
-            if len > 0 {
-                //only one msg
-                if msg_type.len() == 1 {
-                    call_param = msg_type.keys().next().unwrap().to_string();
-                } else {
-                    print!("Input Call param from [ ");
-                    first = true;
-
-                    editor.clear_history();
-                    for k in sorted(msg_type.keys()) {
-                        if first {
-                            first = false;
-                        } else {
-                            print!(" | ")
-                        }
-                        print!("{}", k.green().bold());
-                        editor.add_history_entry(k);
-                    }
-
-                    print!(" ]\n");
-                    call_param.clear();
-
-                    editor.readline(&mut call_param, false);
-                }
+        let json_msg = match msg_type.get(call_param.as_str()) {
+            None => "{}".to_string(),
+            Some(msg) => {
+                analyzer.show_message_type(call_param.as_str(), msg);
+                input_message(
+                    call_param.as_str(),
+                    msg,
+                    &mut repl.editor,
+                    &analyzer,
+                    &is_enum,
+                )
             }
+        };
 
-            let json_msg = match msg_type.get(call_param.as_str()) {
-                None => "{}".to_string(),
-                Some(msg) => {
-                    engine.analyzer.show_message_type(call_param.as_str(), msg);
-                    input_message(call_param.as_str(), msg, engine, &is_enum)
-                }
-            };
-
-            // update previous history entries
-            editor.update_input_history_entry();
+        // update previous history entries
+        repl.editor.update_input_history_entry();
 
-            engine.call(call_type.as_str(), json_msg.as_str(), info);
-        }
+        call_engine(
+            repl,
+            contract_addr,
+            call_type.as_str(),
+            json_msg.as_str(),
+            sender_addr,
+        );
     }
 }
 
 fn simulate_by_json(
-    engine: &mut ContractInstance,
+    repl: &mut Repl,
+    contract_addr: &str,
     sender_addr: &str,
 ) -> Result<(bool, String, String), String> {
-    unsafe {
-        let Config {
-            accounts, editor, ..
-        } = Config::get();
-        let info = match accounts.iter().find(|x| x.sender.as_str().eq(sender_addr)) {
-            Some(i) => i,
-            None => return Err(format!("No account found: {}", sender_addr)),
+    loop {
+        apply_reloads(repl);
+        print_banner(repl, contract_addr, sender_addr);
+        let (call_type, contract_switch, account_switch) = match get_call_type(repl) {
+            None => continue,
+            Some(s) => s,
         };
 
-        loop {
-            println!(
-                "Start_simulate with sender: {}, contract: {}, chain: {}, denom: {}, block height: {}",
-                sender_addr.green().bold(),
-                engine.env.contract.address.to_string().green().bold(),
-                CHAIN_ID.green().bold(), DENOM.green().bold(), BLOCK_HEIGHT.to_string().green().bold()
-            );
-            let (call_type, contract_switch, account_switch) = match get_call_type() {
-                None => continue,
-                Some(s) => s,
-            };
-
-            // default messages
-            if contract_switch {
-                if engine.env.contract.address.to_string().ne(&call_type) {
-                    return Ok((true, call_type, sender_addr.to_string()));
-                }
-                continue;
-            } else if account_switch {
-                // change account
-                if sender_addr.ne(call_type.as_str()) {
-                    return Ok((true, engine.env.contract.address.to_string(), call_type));
-                }
-                continue;
+        // default messages
+        if contract_switch {
+            if contract_addr.ne(&call_type) {
+                return Ok((true, call_type, sender_addr.to_string()));
             }
+            continue;
+        } else if account_switch {
+            // change account
+            if sender_addr.ne(call_type.as_str()) {
+                return Ok((true, contract_addr.to_string(), call_type));
+            }
+            continue;
+        } else if call_type.eq("ibc") {
+            simulate_ibc(repl, contract_addr);
+            continue;
+        } else if call_type.eq("block") {
+            simulate_block(repl);
+            continue;
+        } else if call_type.eq("bank") {
+            simulate_bank(repl);
+            continue;
+        }
 
-            println!("Input json string:");
-            let mut json_msg = String::new();
-            // update previous history entries
+        println!("Input json string:");
+        let mut json_msg = String::new();
+        // update previous history entries
 
-            editor.update_input_history_entry();
-            editor.readline(&mut json_msg, true);
+        repl.editor.update_input_history_entry();
+        repl.editor.readline(&mut json_msg, true);
 
-            engine.call(call_type.as_str(), json_msg.as_str(), info);
-        }
+        call_engine(
+            repl,
+            contract_addr,
+            call_type.as_str(),
+            json_msg.as_str(),
+            sender_addr,
+        );
     }
 }
 
 // start_simulate will return next contract and account to run
 fn start_simulate(
+    repl: &mut Repl,
     contract_addr: &str,
     sender_addr: &str,
 ) -> Result<(bool, String, String), String> {
-    unsafe {
-        let Config { engines, .. } = Config::get();
-        match engines.get_mut(contract_addr) {
-            Some(engine) => {
-                if engine.analyzer.map_of_member.is_empty() {
-                    simulate_by_json(engine, sender_addr)
-                } else {
-                    simulate_by_auto_analyze(engine, sender_addr)
-                }
+    match repl.simulator.analyzer(contract_addr) {
+        Ok(analyzer) => {
+            if analyzer.map_of_member.is_empty() {
+                simulate_by_json(repl, contract_addr, sender_addr)
+            } else {
+                simulate_by_auto_analyze(repl, contract_addr, sender_addr)
             }
-            None => Err(format!("No engine found: {}", contract_addr)),
         }
+        Err(_) => Err(format!("No engine found: {}", contract_addr)),
     }
 }
 
-fn start_simulate_forever(contract_addr: &str, sender_addr: &str) -> bool {
-    match start_simulate(contract_addr, sender_addr) {
+fn start_simulate_forever(repl: &mut Repl, contract_addr: &str, sender_addr: &str) -> bool {
+    match start_simulate(repl, contract_addr, sender_addr) {
         Ok(ret) => {
             if ret.0 {
                 // recursive
-                start_simulate_forever(ret.1.as_str(), ret.2.as_str())
+                start_simulate_forever(repl, ret.1.as_str(), ret.2.as_str())
             } else {
                 println!(
                     "start_simulate failed for contract: {}",
@@ -619,126 +882,230 @@ fn load_artifacts(
     Ok(file_paths)
 }
 
-// handle_contract_response currently support execute only, with new message info from send fund param
-fn handle_contract_response(sender_addr: &str, messages: Vec<CosmosMsg>) -> Vec<Attribute> {
-    let mut attributes: Vec<Attribute> = vec![];
-    unsafe {
-        let Config { engines, .. } = Config::get();
-        for msg in messages {
-            // only clone required properties
-            if let CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr,
-                msg,
-                funds,
-            }) = msg
-            {
-                let result = match engines.get_mut(contract_addr.as_str()) {
-                    None => format!("No such contract: {}", contract_addr),
-                    Some(engine) => {
-                        engine.execute_raw(
-                            msg.as_slice(),
-                            &MessageInfo {
-                                sender: Addr::unchecked(sender_addr),
-                                // there is default account with balance
-                                funds,
-                            },
-                        )
-                    }
-                };
-                attributes.push(Attribute {
-                    key: contract_addr.to_string(),
-                    value: result,
-                })
+fn dump_relayer(relayer: &Relayer) {
+    for channel in relayer.channels.iter() {
+        println!(
+            "{} {} <-> {} {} ({}, {:?}, {})",
+            channel.ends[0].contract_addr.green().bold(),
+            channel.ends[0].endpoint.channel_id,
+            channel.ends[1].contract_addr.green().bold(),
+            channel.ends[1].endpoint.channel_id,
+            channel.version,
+            channel.order,
+            if channel.open { "open" } else { "closed" }
+        );
+    }
+    for packet in relayer.packets.iter() {
+        println!(
+            "{} {} -> {} sequence {}",
+            "[packet]".blue().bold(),
+            packet.src.channel_id,
+            packet.dest.channel_id,
+            packet.sequence
+        );
+    }
+    for (packet, ack) in relayer.acks.iter() {
+        println!(
+            "{} {} -> {} sequence {} = {}",
+            "[ack]".blue().bold(),
+            packet.dest.channel_id,
+            packet.src.channel_id,
+            packet.sequence,
+            ack.to_base64().yellow()
+        );
+    }
+}
+
+// choose_channel asks for a channel owned by the contract
+fn choose_channel(repl: &mut Repl, contract_addr: &str) -> Option<String> {
+    let Repl {
+        simulator, editor, ..
+    } = repl;
+    let relayer = &simulator.chain().relayer;
+    let mut first = true;
+    let mut call_param = String::new();
+    print!("Choose channel [ ");
+
+    editor.clear_history();
+
+    for channel in relayer.channels.iter().filter(|c| c.open) {
+        for end in channel.ends.iter() {
+            if end.contract_addr.ne(contract_addr) {
+                continue;
             }
+            if first {
+                first = false;
+            } else {
+                print!(" | ")
+            }
+            print!("{}", end.endpoint.channel_id.green().bold());
+            editor.add_history_entry(&end.endpoint.channel_id);
         }
     }
 
-    attributes
+    print!(" ]\n");
+
+    editor.readline(&mut call_param, false);
+
+    if relayer.find_end(contract_addr, &call_param).is_none() {
+        println!("Channel {} not existed", call_param.red().bold());
+        return None;
+    }
+    Some(call_param)
 }
 
-fn insert_engine(
-    wasm_file: &str,
-    contract_addr: &str,
-    wasm_handler: WasmHandler,
-    storage: &MockStorage,
-) {
-    match ContractInstance::new_instance(
-        wasm_file,
-        contract_addr,
-        wasm_handler,
-        storage,
-        handle_contract_response,
-    ) {
-        Err(e) => {
-            println!("error occurred during install contract: {}", e.red());
+// input_channel asks for the counterparty contract, the version and the order of a new channel
+fn input_channel(repl: &mut Repl, contract_addr: &str) -> Option<(String, IbcOrder, String)> {
+    let Repl {
+        simulator, editor, ..
+    } = repl;
+    let engines = &simulator.chain().engines;
+    let mut first = true;
+    let mut counterparty = String::new();
+    print!("Choose counterparty contract [ ");
+
+    editor.clear_history();
+
+    for k in sorted(engines.keys()) {
+        if k.eq(contract_addr) {
+            continue;
         }
-        Ok(engine) => {
-            unsafe {
-                let Config { engines, .. } = Config::get();
-                engines.insert(contract_addr.to_owned(), engine)
-            };
+        if first {
+            first = false;
+        } else {
+            print!(" | ")
+        }
+        print!("{}", k.green().bold());
+        editor.add_history_entry(k);
+    }
+
+    print!(" ]\n");
+
+    editor.readline(&mut counterparty, false);
+    let counterparty = simulator.chain().resolve(&counterparty);
+
+    if engines.get(&counterparty).is_none() {
+        println!("Smart contract {} not existed", counterparty.red().bold());
+        return None;
+    }
+
+    println!("Input channel version:");
+    let mut version = String::new();
+    editor.readline(&mut version, false);
+
+    println!(
+        "Input channel order ({} | {}):",
+        "unordered".green().bold(),
+        "ordered".green().bold()
+    );
+    editor.update_history_entries(vec!["unordered".to_string(), "ordered".to_string()]);
+    let mut order = String::new();
+    editor.readline(&mut order, false);
+    let order = match order.as_str() {
+        "" | "unordered" => IbcOrder::Unordered,
+        "ordered" => IbcOrder::Ordered,
+        _ => {
+            println!("Wrong channel order {}", order.red().bold());
+            return None;
+        }
+    };
+
+    Some((counterparty, order, version))
+}
+
+// simulate_ibc runs one step of the local relayer as a transaction of the current block
+fn simulate_ibc(repl: &mut Repl, contract_addr: &str) {
+    let actions = ["open", "relay", "ack", "timeout", "close", "packets"];
+    println!(
+        "Choose ibc action [ {} ]",
+        actions
+            .iter()
+            .map(|a| a.green().bold().to_string())
+            .collect::<Vec<String>>()
+            .join(" | ")
+    );
+
+    let mut action = String::new();
+    repl.editor
+        .update_history_entries(actions.iter().map(|a| a.to_string()).collect());
+    repl.editor.readline(&mut action, false);
+
+    let result = match action.as_str() {
+        "open" => match input_channel(repl, contract_addr) {
+            None => Err("no channel".to_string()),
+            Some((counterparty, order, version)) => repl
+                .simulator
+                .deliver_tx(|sim| sim.open_channel(contract_addr, &counterparty, order, &version)),
+        },
+        "relay" => repl.simulator.deliver_tx(|sim| sim.relay_packet()),
+        "ack" => repl.simulator.deliver_tx(|sim| sim.ack_packet()),
+        "timeout" => repl.simulator.deliver_tx(|sim| sim.timeout_packet()),
+        "close" => match choose_channel(repl, contract_addr) {
+            None => Err("no channel".to_string()),
+            Some(channel_id) => repl
+                .simulator
+                .deliver_tx(|sim| sim.close_channel(contract_addr, &channel_id)),
+        },
+        "packets" => {
+            dump_relayer(&repl.simulator.chain().relayer);
+            return;
         }
+        _ => Err(format!("Wrong ibc action {}", action)),
     };
+
+    match result {
+        Ok(response) => {
+            ContractInstance::dump_events(&response.events, response.data.as_ref());
+            println!("{}", format!("{} succeeded", action).green().bold());
+        }
+        Err(err) => {
+            println!("{}", err.red());
+            println!("{}", "Transaction rolled back".red().bold());
+        }
+    }
 }
 
+// watch_and_update sends the wasm files modified on disk, the terminal reloads them before its next prompt
 fn watch_and_update(
-    sender: &sync::mpsc::Sender<String>,
+    sender: &Sender<(String, String)>,
     wasm_files: &Vec<(String, String)>,
 ) -> Result<bool, Error> {
     // do not copy, use reference when loop
-    let len = wasm_files.len();
-    let mut modified_files: Vec<time::SystemTime> = vec![time::SystemTime::now(); len];
-    unsafe {
-        let Config { engines, .. } = Config::get();
-        loop {
-            for index in 0..len {
-                let (wasm_file, contract_addr) = &wasm_files[index];
-
-                if let Ok(modified_time) = fs::metadata(wasm_file)?.modified() {
-                    if modified_time.eq(&modified_files[index]) {
-                        continue;
-                    }
-                    modified_files[index] = modified_time;
-                }
+    let mut modified_files = vec![];
+    for (wasm_file, _) in wasm_files {
+        modified_files.push(fs::metadata(wasm_file)?.modified()?);
+    }
+    loop {
+        // watch again every second
+        thread::sleep(time::Duration::from_millis(1000));
 
-                match engines.get_mut(contract_addr) {
-                    Some(eng) => {
-                        // sleep 100 miliseconds incase it notifies modification before build version is completed
-                        thread::sleep(time::Duration::from_millis(100));
-                        // callback query directly from storage to copy it
-                        eng.instance
-                            .with_storage(|storage| {
-                                insert_engine(wasm_file, contract_addr, query_wasm, storage);
-                                Ok(())
-                            })
-                            .unwrap();
-                    }
-                    None => {
-                        insert_engine(
-                            wasm_file,
-                            contract_addr,
-                            query_wasm,
-                            &contract_vm::mock::MockStorage::default(),
-                        );
-                    }
-                };
+        for (index, (wasm_file, contract_addr)) in wasm_files.iter().enumerate() {
+            let modified_time = fs::metadata(wasm_file)?.modified()?;
+            if modified_time.eq(&modified_files[index]) {
+                continue;
             }
+            modified_files[index] = modified_time;
 
-            // init all contracts first time, send the first contract to notify
-            if len > 0 {
-                sender.send(wasm_files[0].1.to_owned()).unwrap();
+            // sleep 100 miliseconds incase it notifies modification before build version is completed
+            thread::sleep(time::Duration::from_millis(100));
+            if sender
+                .send((wasm_file.to_owned(), contract_addr.to_owned()))
+                .is_err()
+            {
+                return Ok(true);
             }
-
-            // watch again every second
-            thread::sleep(time::Duration::from_millis(1000));
         }
     }
 }
 
+// CointBalance is the initial balance of an account, amount is in the default denom
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct CointBalance {
     pub address: Addr,
-    pub amount: Uint128,
+    #[serde(default)]
+    pub amount: Option<Uint128>,
+    #[serde(default)]
+    pub coins: Vec<Coin>,
 }
 
 fn prepare_command_line() -> bool {
@@ -755,51 +1122,257 @@ fn prepare_command_line() -> bool {
             "-c, --contract=[CONTRACT_FOLDER] 'Other contract folder'",
         ))
         .arg(
-            Arg::from_usage("-b, --balance=[COIN_BALANCE] 'Other coin balance, multiple'")
+            Arg::from_usage(
+                "-b, --balance=[COIN_BALANCE] 'Other coin balance like {\"address\",\"amount\",\"coins\":[{\"denom\",\"amount\"}]}, multiple'",
+            )
+                .multiple(true),
+        )
+        .arg(Arg::from_usage(
+            "-s, --staking=[STAKING_FILE] 'Staking fixture with validators and delegations'",
+        ))
+        .arg(
+            Arg::from_usage("-q, --query=[QUERY_FILE] 'Custom query fixture, multiple'")
                 .multiple(true),
         )
+        .arg(
+            Arg::from_usage("-m, --msg=[MSG_FILE] 'Custom message fixture, multiple'")
+                .multiple(true),
+        )
+        .arg(
+            Arg::from_usage(
+                "-p, --proto=[DESCRIPTOR_SET] 'Protobuf descriptor set of stargate types, multiple'",
+            )
+            .multiple(true),
+        )
+        .arg(Arg::from_usage("--height=[HEIGHT] 'Block height to start at'"))
+        .arg(Arg::from_usage(
+            "--time=[SECONDS] 'Block time to start at, in seconds since epoch'",
+        ))
+        .arg(Arg::from_usage("--chain-id=[CHAIN_ID] 'Chain id of the block'"))
+        .arg(Arg::from_usage(
+            "--prefix=[PREFIX] 'Bech32 prefix of the addresses, orai by default'",
+        ))
+        .arg(Arg::from_usage(
+            "--block-interval=[SECONDS] 'Seconds between two blocks'",
+        ))
+        .arg(Arg::from_usage(
+            "--no-auto-commit 'Keep the calls in the current block until it is committed'",
+        ))
+        .arg(Arg::from_usage(
+            "--script=[SCRIPT_FILE] 'Run the json steps of a scenario script without prompts'",
+        ))
         .get_matches();
 
+    let simulator = Simulator::new();
     let default_addr;
 
-    unsafe {
-        let Config { accounts, .. } = Config::get();
+    {
+        let chain = &mut *simulator.chain_mut();
+
+        // addresses are made with the prefix from now on
+        if let Some(prefix) = matches.value_of("prefix") {
+            if let Err(err) = chain.set_bech32_prefix(prefix) {
+                println!("wrong prefix {}", err.red());
+                return false;
+            }
+        }
+
+        // block to start at, before the staking fixture which starts its delegations there
+        for (name, value) in [
+            ("height", &mut chain.block.height),
+            ("block-interval", &mut chain.block_interval),
+        ] {
+            if let Some(arg) = matches.value_of(name) {
+                match arg.parse::<u64>() {
+                    Ok(number) => *value = number,
+                    Err(_) => {
+                        println!("wrong {} {}", name, arg.red());
+                        return false;
+                    }
+                }
+            }
+        }
+        if let Some(time) = matches.value_of("time") {
+            match time.parse::<u64>() {
+                Ok(time) => chain.block.time = Timestamp::from_seconds(time),
+                Err(_) => {
+                    println!("wrong time {}", time.red());
+                    return false;
+                }
+            }
+        }
+        if let Some(chain_id) = matches.value_of("chain-id") {
+            chain.block.chain_id = chain_id.to_string();
+        }
+        chain.auto_commit = !matches.is_present("no-auto-commit");
+        // the terminal shows the storage changes of every call
+        chain.log_storage = true;
+        chain.staking.set_height(chain.block.height);
+
+        // protobuf types of the stargate queries and messages, protoc --descriptor_set_out
+        if let Some(files) = matches.values_of("proto") {
+            for file in files {
+                if let Err(err) = fs::read(file)
+                    .map_err(|e| e.to_string())
+                    .and_then(|data| chain.stargate.add_descriptor_set(&data))
+                {
+                    println!("can not load descriptor set {}: {}", file, err.red());
+                    return false;
+                }
+            }
+        }
+
+        // responses of the chain custom queries, first matching request wins
+        if let Some(files) = matches.values_of("query") {
+            for file in files {
+                let fixtures = match fs::read(file).map_err(|e| e.to_string()).and_then(|data| {
+                    serde_json::from_slice::<Vec<SpecialQueryFixture>>(&data)
+                        .map_err(|e| e.to_string())
+                }) {
+                    Err(err) => {
+                        println!("can not load custom query fixture {}: {}", file, err.red());
+                        return false;
+                    }
+                    Ok(fixtures) => fixtures,
+                };
+                chain.special_queries.extend(fixtures);
+            }
+        }
+
+        // effects of the contract custom messages, first matching message wins
+        if let Some(files) = matches.values_of("msg") {
+            for file in files {
+                let fixtures = match fs::read(file).map_err(|e| e.to_string()).and_then(|data| {
+                    serde_json::from_slice::<Vec<SpecialMsgFixture>>(&data)
+                        .map_err(|e| e.to_string())
+                }) {
+                    Err(err) => {
+                        println!(
+                            "can not load custom message fixture {}: {}",
+                            file,
+                            err.red()
+                        );
+                        return false;
+                    }
+                    Ok(fixtures) => fixtures,
+                };
+                chain.special_msgs.extend(fixtures);
+            }
+        }
 
         // add more balances
         if let Some(coin_balances) = matches.values_of("balance") {
             for file in coin_balances.collect::<Vec<&str>>() {
-                let coin_balance: CointBalance = from_slice(file.as_bytes()).unwrap();
-                // add sent_funds if not zero
-                let funds = match coin_balance.amount.is_zero() {
-                    true => vec![],
-                    false => vec![Coin {
-                        denom: DENOM.to_string(),
-                        amount: coin_balance.amount,
-                    }],
+                let CointBalance {
+                    address,
+                    amount,
+                    mut coins,
+                } = match from_slice(file.as_bytes()) {
+                    Err(err) => {
+                        println!("wrong balance {}: {}", file, err.to_string().red());
+                        return false;
+                    }
+                    Ok(coin_balance) => coin_balance,
                 };
-                accounts.push(MessageInfo {
-                    sender: coin_balance.address,
-                    funds,
-                });
+                if let Some(amount) = amount {
+                    coins.push(Coin {
+                        denom: DENOM.to_string(),
+                        amount,
+                    });
+                }
+                // names like alice are given an address
+                let address = chain.add_alias(address.as_str());
+                chain.bank.set_balance(&address, coins);
+                chain.accounts.push(address);
             }
         }
 
         // default account
-        if accounts.is_empty() {
-            accounts.push(MessageInfo {
-                sender: Addr::unchecked(format!("{}{}", DENOM, DEFAULT_SENDER_ADDR)),
-                // there is default account with balance
-                funds: vec![Coin {
+        if chain.accounts.is_empty() {
+            let sender = chain.add_alias(DEFAULT_SENDER_ADDR);
+            // there is default account with balance
+            chain.bank.set_balance(
+                &sender,
+                vec![Coin {
                     denom: DENOM.to_string(),
                     amount: Uint128::from(DEFAULT_SENDER_BALANCE),
                 }],
-            });
+            );
+            chain.accounts.push(sender);
+        }
+
+        // validators and delegations of the simulated chain, once the accounts have their
+        // address, delegators can be names like the balances
+        if let Some(file) = matches.value_of("staking") {
+            let mut fixture = match fs::read(file)
+                .map_err(|e| e.to_string())
+                .and_then(|data| from_slice::<StakingFixture>(&data).map_err(|e| e.to_string()))
+            {
+                Err(err) => {
+                    println!("can not load staking fixture {}: {}", file, err.red());
+                    return false;
+                }
+                Ok(fixture) => fixture,
+            };
+            for delegation in fixture.delegations.iter_mut() {
+                delegation.delegator =
+                    Addr::unchecked(chain.add_alias(delegation.delegator.as_str()));
+            }
+            chain.staking = StakingQuerier::from_fixture(DENOM, chain.block.height, fixture);
         }
 
         // Sort by sender address
-        accounts.sort_by(|a, b| a.sender.cmp(&b.sender));
+        chain.accounts.sort();
+        chain.accounts.dedup();
         // set default addr
-        default_addr = accounts[0].sender.as_str();
+        default_addr = chain.accounts[0].to_owned();
+    }
+
+    // scenario scripts run the contracts given on the command line without prompts
+    if let Some(script_file) = matches.value_of("script") {
+        let steps = match fs::read(script_file)
+            .map_err(|e| e.to_string())
+            .and_then(|data| {
+                serde_json::from_slice::<Vec<serde_json::Value>>(&data).map_err(|e| e.to_string())
+            }) {
+            Err(err) => {
+                println!("can not load script {}: {}", script_file, err.red());
+                return false;
+            }
+            Ok(steps) => steps,
+        };
+        let mut contract_addr = String::new();
+        if let Some(file) = matches.value_of("run") {
+            let wasm_files = match load_artifacts(file, matches.value_of("contract")) {
+                Err(e) => {
+                    println!("can not load contract {}: {}", file, e.to_string().red());
+                    return false;
+                }
+                Ok(s) => s,
+            };
+            // calls go to the first contract until the script switches
+            for (wasm_file, name) in wasm_files.iter() {
+                match simulator.load_contract(wasm_file, name, &default_addr) {
+                    Ok(addr) if contract_addr.is_empty() => contract_addr = addr,
+                    Ok(_) => {}
+                    Err(e) => {
+                        println!("error occurred during install contract: {}", e.red());
+                        return false;
+                    }
+                }
+            }
+        }
+
+        return match Script::new(&simulator, &contract_addr, &default_addr).run(&steps) {
+            Ok(()) => {
+                println!("{}", "Script passed".green().bold());
+                true
+            }
+            Err(err) => {
+                println!("{}", err.red());
+                false
+            }
+        };
     }
 
     if let Some(file) = matches.value_of("run") {
@@ -809,6 +1382,20 @@ fn prepare_command_line() -> bool {
             Ok(s) => s,
         };
 
+        // first load, register the code and the contract using it, the folder name
+        // of the contract stands for its address
+        let mut contract_addrs = vec![];
+        for (wasm_file, name) in wasm_files.iter() {
+            match simulator.load_contract(wasm_file, name, &default_addr) {
+                Ok(contract_addr) => contract_addrs.push(contract_addr),
+                Err(e) => println!("error occurred during install contract: {}", e.red()),
+            }
+        }
+        let contract_addr = match contract_addrs.first() {
+            None => return false,
+            Some(contract_addr) => contract_addr.to_owned(),
+        };
+
         let (sender, receiver) = sync::mpsc::channel();
         // Spawn off an expensive computation
         thread::spawn(move || {
@@ -818,34 +1405,34 @@ fn prepare_command_line() -> bool {
             return true;
         });
 
-        // simulate until break, start with first contract
-        match receiver.recv() {
-            Ok(contract_addr) => {
-                unsafe {
-                    let Config {
-                        accounts,
-                        engines,
-                        editor,
-                    } = Config::get();
-                    // init the first suggested items
-                    for k in accounts.iter() {
-                        editor.add_input_history_entry(k.sender.to_string());
-                    }
-                    for k in engines.keys() {
-                        editor.add_input_history_entry(k.to_owned());
-                    }
-                }
-                return start_simulate_forever(contract_addr.as_str(), default_addr);
+        let mut repl = Repl {
+            simulator,
+            editor: TerminalEditor::new(),
+            reloads: receiver,
+        };
+        {
+            let Repl {
+                simulator, editor, ..
+            } = &mut repl;
+            let chain = simulator.chain();
+            // init the first suggested items
+            for k in chain.accounts.iter() {
+                editor.add_input_history_entry(k.to_owned());
             }
-            Err(e) => {
-                println!("watch error: {}", e.to_string().red());
-                return false;
+            for k in chain.engines.keys() {
+                editor.add_input_history_entry(k.to_owned());
             }
         }
+
+        // simulate until break, start with first contract
+        return start_simulate_forever(&mut repl, &contract_addr, &default_addr);
     }
     return false;
 }
 
 fn main() {
-    prepare_command_line();
+    // a failing script fails the process, for CI
+    if !prepare_command_line() {
+        process::exit(1);
+    }
 }